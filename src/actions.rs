@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+// Everything Talan knows about a crafting action. Potencies and rates are
+// percentages, and durability is the cost before Waste Not.
+#[derive(Debug, PartialEq)]
+pub struct ActionInfo {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub specialist: bool, // requires the job to be a specialist
+    pub level: u32,
+    pub cp: u32,
+    pub durability: u32,
+    pub progress: u32,
+    pub quality: u32,
+    pub success: u32,
+    pub buff: u32, // steps the effect lasts, 0 if it has none
+    pub gcd: bool, // uses a step of the craft and triggers the GCD
+}
+
+// Buffs which last until the synthesis is finished
+pub const WHOLE_CRAFT: u32 = u32::MAX;
+
+const BASE: ActionInfo = ActionInfo {
+    name: "",
+    aliases: &[],
+    specialist: false,
+    level: 1,
+    cp: 0,
+    durability: 0,
+    progress: 0,
+    quality: 0,
+    success: 100,
+    buff: 0,
+    gcd: true,
+};
+
+// The Stormblood crafting actions
+#[rustfmt::skip]
+pub static ACTIONS: &[ActionInfo] = &[
+    // Actions every crafter learns
+    ActionInfo { name: "Basic Synthesis", level: 1, durability: 10, progress: 100, success: 90, ..BASE },
+    ActionInfo { name: "Basic Touch", level: 5, cp: 18, durability: 10, quality: 100, success: 70, ..BASE },
+    ActionInfo { name: "Master's Mend", level: 7, cp: 92, ..BASE },
+    ActionInfo { name: "Steady Hand", level: 9, cp: 22, buff: 5, ..BASE },
+    ActionInfo { name: "Inner Quiet", level: 11, cp: 18, buff: WHOLE_CRAFT, ..BASE },
+    ActionInfo { name: "Observe", level: 13, cp: 7, ..BASE },
+    ActionInfo { name: "Standard Touch", level: 18, cp: 32, durability: 10, quality: 125, success: 80, ..BASE },
+    ActionInfo { name: "Great Strides", level: 21, cp: 32, buff: 3, ..BASE },
+    ActionInfo { name: "Master's Mend II", aliases: &["Master's Mend 2"], level: 25, cp: 160, ..BASE },
+    ActionInfo { name: "Standard Synthesis", level: 31, cp: 15, durability: 10, progress: 150, success: 90, ..BASE },
+    ActionInfo { name: "Advanced Touch", level: 43, cp: 48, durability: 10, quality: 150, success: 90, ..BASE },
+    ActionInfo { name: "Collectable Synthesis", level: 50, gcd: false, ..BASE },
+    ActionInfo { name: "Byregot's Brow", level: 51, cp: 18, durability: 10, quality: 150, success: 90, ..BASE },
+    ActionInfo { name: "Precise Touch", level: 53, cp: 18, durability: 10, quality: 100, success: 70, ..BASE },
+    ActionInfo { name: "Hasty Touch II", aliases: &["Hasty Touch 2"], level: 61, cp: 5, durability: 10, quality: 100, success: 80, ..BASE },
+    ActionInfo { name: "Careful Synthesis III", aliases: &["Careful Synthesis 3"], level: 62, cp: 7, durability: 10, progress: 150, ..BASE },
+    ActionInfo { name: "Rapid Synthesis II", aliases: &["Rapid Synthesis 2"], level: 63, cp: 12, durability: 10, progress: 300, success: 60, ..BASE },
+    ActionInfo { name: "Patient Touch", level: 64, cp: 6, durability: 10, quality: 100, success: 50, ..BASE },
+    ActionInfo { name: "Manipulation II", aliases: &["Manipulation 2"], level: 65, cp: 96, buff: 8, ..BASE },
+    ActionInfo { name: "Prudent Touch", level: 66, cp: 21, durability: 5, quality: 100, success: 70, ..BASE },
+    ActionInfo { name: "Focused Synthesis", level: 67, cp: 5, durability: 10, progress: 200, success: 50, ..BASE },
+    ActionInfo { name: "Focused Touch", level: 68, cp: 18, durability: 10, quality: 150, success: 50, ..BASE },
+
+    // Specialist actions, used with a Soul of the Crafter
+    ActionInfo { name: "Innovative Touch", specialist: true, level: 56, cp: 8, durability: 10, quality: 100, success: 40, buff: 3, ..BASE },
+    ActionInfo { name: "Nymeia's Wheel", specialist: true, level: 60, cp: 18, ..BASE },
+    ActionInfo { name: "Trained Hand", specialist: true, level: 60, cp: 32, durability: 10, progress: 150, quality: 150, ..BASE },
+    ActionInfo { name: "Initial Preparations", specialist: true, level: 69, cp: 50, buff: WHOLE_CRAFT, ..BASE },
+    ActionInfo { name: "Specialty: Reinforce", aliases: &["Reinforce"], specialist: true, level: 69, ..BASE },
+    ActionInfo { name: "Specialty: Refurbish", aliases: &["Refurbish"], specialist: true, level: 69, ..BASE },
+    ActionInfo { name: "Specialty: Reflect", aliases: &["Reflect"], specialist: true, level: 69, ..BASE },
+    ActionInfo { name: "Byregot's Miracle", specialist: true, level: 70, cp: 24, durability: 10, quality: 100, success: 70, ..BASE },
+
+    // Job actions, grouped by the job that learns them. role_actions.json
+    // says which jobs learn each one and at what level, and so where it's a
+    // role action.
+    ActionInfo { name: "Rumination", level: 15, ..BASE },
+    ActionInfo { name: "Brand of Wind", level: 37, cp: 15, durability: 10, progress: 100, success: 90, ..BASE },
+    ActionInfo { name: "Byregot's Blessing", level: 50, cp: 24, durability: 10, quality: 100, success: 90, ..BASE },
+    ActionInfo { name: "Name of Wind", level: 54, cp: 15, buff: 5, ..BASE },
+
+    ActionInfo { name: "Ingenuity", level: 15, cp: 24, buff: 5, ..BASE },
+    ActionInfo { name: "Brand of Fire", level: 37, cp: 15, durability: 10, progress: 100, success: 90, ..BASE },
+    ActionInfo { name: "Ingenuity II", aliases: &["Ingenuity 2"], level: 50, cp: 32, buff: 5, ..BASE },
+    ActionInfo { name: "Name of Fire", level: 54, cp: 15, buff: 5, ..BASE },
+
+    ActionInfo { name: "Rapid Synthesis", level: 15, durability: 10, progress: 250, success: 50, ..BASE },
+    ActionInfo { name: "Brand of Ice", level: 37, cp: 15, durability: 10, progress: 100, success: 90, ..BASE },
+    ActionInfo { name: "Piece by Piece", level: 50, cp: 15, durability: 10, success: 90, ..BASE },
+    ActionInfo { name: "Name of Ice", level: 54, cp: 15, buff: 5, ..BASE },
+
+    ActionInfo { name: "Manipulation", level: 15, cp: 88, buff: 3, ..BASE },
+    ActionInfo { name: "Flawless Synthesis", level: 37, cp: 15, durability: 10, success: 90, ..BASE },
+    ActionInfo { name: "Innovation", level: 50, cp: 18, buff: 3, ..BASE },
+    ActionInfo { name: "Maker's Mark", level: 54, cp: 20, ..BASE },
+
+    ActionInfo { name: "Waste Not", level: 15, cp: 56, buff: 4, ..BASE },
+    ActionInfo { name: "Brand of Earth", level: 37, cp: 15, durability: 10, progress: 100, success: 90, ..BASE },
+    ActionInfo { name: "Waste Not II", aliases: &["Waste Not 2"], level: 50, cp: 98, buff: 8, ..BASE },
+    ActionInfo { name: "Name of Earth", level: 54, cp: 15, buff: 5, ..BASE },
+
+    ActionInfo { name: "Careful Synthesis", level: 15, durability: 10, progress: 90, ..BASE },
+    ActionInfo { name: "Brand of Lightning", level: 37, cp: 15, durability: 10, progress: 100, success: 90, ..BASE },
+    ActionInfo { name: "Careful Synthesis II", aliases: &["Careful Synthesis 2"], level: 50, durability: 10, progress: 120, ..BASE },
+    ActionInfo { name: "Name of Lightning", level: 54, cp: 15, buff: 5, ..BASE },
+
+    ActionInfo { name: "Tricks of the Trade", level: 15, ..BASE },
+    ActionInfo { name: "Brand of Water", level: 37, cp: 15, durability: 10, progress: 100, success: 90, ..BASE },
+    ActionInfo { name: "Comfort Zone", level: 50, cp: 66, buff: 10, ..BASE },
+    ActionInfo { name: "Name of Water", level: 54, cp: 15, buff: 5, ..BASE },
+
+    ActionInfo { name: "Hasty Touch", level: 15, durability: 10, quality: 100, success: 60, ..BASE },
+    ActionInfo { name: "Steady Hand II", aliases: &["Steady Hand 2"], level: 37, cp: 25, buff: 5, ..BASE },
+    ActionInfo { name: "Reclaim", level: 50, cp: 55, ..BASE },
+    ActionInfo { name: "Muscle Memory", level: 54, cp: 6, durability: 10, ..BASE },
+];
+
+lazy_static::lazy_static! {
+    static ref BY_NAME: HashMap<String, &'static ActionInfo> = {
+        let mut h = HashMap::new();
+        for action in ACTIONS {
+            h.insert(normalize(action.name), action);
+            for alias in action.aliases {
+                h.insert(normalize(alias), action);
+            }
+        }
+        h
+    };
+}
+
+// Macros copied out of the game or a browser sometimes carry typographic
+// apostrophes, so those are folded in along with case.
+fn normalize(name: &str) -> String {
+    name.trim().replace('\u{2019}', "'").to_lowercase()
+}
+
+// Find an action by its name or one of its aliases, ignoring case
+pub fn lookup(name: &str) -> Option<&'static ActionInfo> {
+    BY_NAME.get(&normalize(name)).cloned()
+}
+
+// The closest known action name to a misspelled one, if any is near enough
+// that it's likely what was meant.
+pub fn suggest(name: &str) -> Option<&'static str> {
+    let name = normalize(name);
+    let limit = std::cmp::max(2, name.chars().count() / 3);
+    BY_NAME
+        .iter()
+        .map(|(known, action)| (edit_distance(&name, known), action.name))
+        .filter(|&(distance, _)| distance <= limit)
+        .min()
+        .map(|(_, name)| name)
+}
+
+// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + if ca == cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup_actions() {
+        let action = lookup("careful synthesis 3").unwrap();
+        assert_eq!(action.name, "Careful Synthesis III");
+        assert_eq!(action.cp, 7);
+        assert_eq!(action.progress, 150);
+
+        let action = lookup("Byregot\u{2019}s Blessing").unwrap();
+        assert_eq!(action.name, "Byregot's Blessing");
+
+        assert!(lookup("Reflect").unwrap().specialist);
+        assert!(!lookup("Collectable Synthesis").unwrap().gcd);
+        assert_eq!(lookup("Inovation"), None);
+    }
+
+    #[test]
+    fn suggest_actions() {
+        assert_eq!(suggest("Inovation"), Some("Innovation"));
+        assert_eq!(suggest("byregots blessing"), Some("Byregot's Blessing"));
+        assert_eq!(suggest("Manipulation 3"), Some("Manipulation II"));
+        assert_eq!(suggest("Teleport"), None);
+    }
+
+    #[test]
+    fn unique_names() {
+        let names: usize = ACTIONS.iter().map(|a| 1 + a.aliases.len()).sum();
+        assert_eq!(names, BY_NAME.len());
+    }
+}
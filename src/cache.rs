@@ -0,0 +1,222 @@
+use failure::Error;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::form_urlencoded;
+
+// How the cache is used for a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    Normal,  // use fresh entries, fetch anything missing or past its TTL
+    Offline, // only use cached entries, however old
+    Refresh, // fetch everything again and update the cache
+}
+
+// Responses from GarlandTools saved on disk. Every entry is a JSON file
+// under a directory for its kind, e.g. `search/crimson+cider.json` or
+// `item/19881.json`, holding the response and when it was fetched.
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    mode: CacheMode,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
+struct CacheEntry {
+    fetched: u64, // seconds since the unix epoch
+    data: Value,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(dir: P, ttl: Duration, mode: CacheMode) -> Cache {
+        Cache {
+            dir: dir.into(),
+            ttl,
+            mode,
+        }
+    }
+
+    fn path(&self, kind: &str, key: &str) -> PathBuf {
+        let key: String = form_urlencoded::byte_serialize(key.to_lowercase().as_bytes()).collect();
+        self.dir.join(kind).join(format!("{}.json", key))
+    }
+
+    fn read(&self, path: &Path) -> Option<CacheEntry> {
+        let buffer = fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&buffer) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log::warn!("ignoring corrupt cache entry `{}`: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn write(&self, path: &Path, data: &Value) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let entry = CacheEntry {
+            fetched: now(),
+            data: data.clone(),
+        };
+        fs::write(path, serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
+
+    // Look up `key` of the given kind, calling `fetch` for it when the cache
+    // can't be used. A failed fetch falls back to a stale entry if there is
+    // one.
+    pub fn get_or_fetch<F>(&self, kind: &str, key: &str, fetch: F) -> Result<Value, Error>
+    where
+        F: FnOnce() -> Result<Value, Error>,
+    {
+        let path = self.path(kind, key);
+        let cached = match self.mode {
+            CacheMode::Refresh => None,
+            _ => self.read(&path),
+        };
+
+        if let Some(ref entry) = cached {
+            let age = Duration::from_secs(now().saturating_sub(entry.fetched));
+            if self.mode == CacheMode::Offline || age <= self.ttl {
+                log::trace!("cache hit `{}`", path.display());
+                return Ok(entry.data.clone());
+            }
+        } else if self.mode == CacheMode::Offline {
+            return Err(failure::format_err!(
+                "{} `{}` is not cached, run once without --offline to fetch it",
+                kind,
+                key
+            ));
+        }
+
+        match fetch() {
+            Ok(data) => {
+                if let Err(e) = self.write(&path, &data) {
+                    log::warn!("failed to cache `{}`: {}", path.display(), e);
+                }
+                Ok(data)
+            }
+            Err(e) => match cached {
+                Some(entry) => {
+                    log::warn!("{}, using stale cache entry for {} `{}`", e, kind, key);
+                    Ok(entry.data)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    // Delete every cached entry, returning how many were removed
+    pub fn purge(&self) -> Result<usize, Error> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let mut removed = 0;
+        for kind in fs::read_dir(&self.dir)? {
+            let kind = kind?.path();
+            if !kind.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&kind)? {
+                let entry = entry?.path();
+                if entry.extension().is_some_and(|ext| ext == "json") {
+                    fs::remove_file(entry)?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    // A scratch cache directory, removed when dropped
+    struct ScratchDir(PathBuf);
+
+    impl ScratchDir {
+        fn new(name: &str) -> ScratchDir {
+            let dir = std::env::temp_dir().join(format!("talan-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            ScratchDir(dir)
+        }
+    }
+
+    impl Drop for ScratchDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn unreachable() -> Result<Value, Error> {
+        Err(failure::format_err!("network unavailable"))
+    }
+
+    #[test]
+    fn cache_fixture_offline() {
+        let cache = Cache::new("src/test_cache", Duration::from_secs(0), CacheMode::Offline);
+        let data = cache
+            .get_or_fetch("search", "Crimson Cider", unreachable)
+            .unwrap();
+        assert_eq!(data[0]["id"], "19881");
+        let err = cache
+            .get_or_fetch("search", "Grade 3 Infusion of Strength", unreachable)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "search `Grade 3 Infusion of Strength` is not cached, run once without --offline to fetch it"
+        );
+    }
+
+    #[test]
+    fn cache_ttl_and_refresh() {
+        let scratch = ScratchDir::new("cache-ttl");
+        let hour = Duration::from_secs(3600);
+
+        let cache = Cache::new(&scratch.0, hour, CacheMode::Normal);
+        let data = cache
+            .get_or_fetch("item", "1", || Ok(json!({"v": 1})))
+            .unwrap();
+        assert_eq!(data["v"], 1);
+        // Fresh entries don't need the network
+        let data = cache.get_or_fetch("item", "1", unreachable).unwrap();
+        assert_eq!(data["v"], 1);
+
+        // Refreshing always fetches
+        let cache = Cache::new(&scratch.0, hour, CacheMode::Refresh);
+        let data = cache
+            .get_or_fetch("item", "1", || Ok(json!({"v": 2})))
+            .unwrap();
+        assert_eq!(data["v"], 2);
+
+        // Expired entries are fetched again, but still used if that fails
+        let cache = Cache::new(&scratch.0, Duration::from_secs(0), CacheMode::Normal);
+        fs::write(
+            cache.path("item", "1"),
+            r#"{"fetched": 0, "data": {"v": 2}}"#,
+        )
+        .unwrap();
+        let data = cache.get_or_fetch("item", "1", unreachable).unwrap();
+        assert_eq!(data["v"], 2);
+        let data = cache
+            .get_or_fetch("item", "1", || Ok(json!({"v": 3})))
+            .unwrap();
+        assert_eq!(data["v"], 3);
+
+        assert_eq!(cache.purge().unwrap(), 1);
+        assert!(cache.get_or_fetch("item", "1", unreachable).is_err());
+    }
+}
@@ -1,29 +1,210 @@
+use crate::crystal::{self, Crystal};
+use crate::hq::Presses;
+use crate::keybinds::Keybinds;
 use crate::macros;
-use crate::role_actions::RoleActions;
+use crate::role_actions::{self, Command, Plan, RoleActions, SavedSlots, Stage};
+use crate::sim::{self, Finish, Outcome};
 use crate::task::Task;
 use crate::ui;
-use log;
+use failure::Error;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::Duration;
 
-// Runs through the set of tasks
-// TODO: make it actually run more than one task
-pub fn craft_items(window: ui::WinHandle, tasks: &[Task]) {
-    // TODO: this will be a problem when we run multiple tasks
+// How crafting actions are sent to the game. Typed actions go through the
+// chat box as `/ac` commands and need no setup, while hotbar actions press
+// the keybind from `[crafting_keybinds]`, which is much faster for long
+// action names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionMode {
+    Typed,
+    Hotbar,
+}
+
+// Runs through the set of tasks in order, as long as every one of their
+// macros can complete its recipe with the role action slots there are. Role
+// actions start from the saved slots, and the slots are saved to
+// `state_file` again as they change. Tasks that don't switch gearsets run
+// on whatever job is active, so their slots aren't saved.
+pub fn craft_items(
+    window: &ui::WinHandle,
+    tasks: &[Task],
+    mode: ActionMode,
+    mut saved: SavedSlots,
+    state_file: &Path,
+) -> Result<(), Error> {
+    check_role_actions(tasks)?;
+    preflight(tasks)?;
+    let plan = role_actions::plan(&role_action_stages(tasks), &saved);
+    log::info!(
+        "role actions need {} /aaction command(s), {} fewer than an LRU cache",
+        plan.count(),
+        plan.saved()
+    );
+    let save_slots = |i: usize| {
+        if tasks[i].gearset == 0 {
+            return;
+        }
+        let job = match tasks[i].item.recipe.job {
+            Some(job) => job,
+            None => return,
+        };
+        saved.set(job, plan.slots[i].clone());
+        if let Err(e) = saved.save(state_file) {
+            log::warn!("failed to save role actions: {}", e);
+        }
+    };
+    run_tasks(window, tasks, mode, &plan, save_slots, |_| {});
+    Ok(())
+}
+
+// Simulate the task's macro with the stats of its gearset. Returns None when
+// either the stats or the recipe's parameters aren't known.
+fn simulate_task(task: &Task) -> Option<Outcome> {
+    let crafter = task.crafter.as_ref()?;
+    let recipe = task.item.recipe.synthesis()?;
+    Some(sim::simulate(&recipe, crafter, task.actions()))
+}
+
+// Explain why a simulated craft would waste its materials, if it would
+fn outcome_problem(outcome: &Outcome, task: &Task) -> Option<String> {
+    let required = task.item.recipe.progress;
+    let problem = match outcome.finish {
+        Finish::Broken => format!(
+            "the craft breaks at step {} (line {}) with {}/{} progress",
+            outcome.step, outcome.line, outcome.progress, required
+        ),
+        Finish::Incomplete => format!(
+            "the macro ends with only {}/{} progress",
+            outcome.progress, required
+        ),
+        Finish::Complete => match outcome.skipped.iter().find(|s| s.reason == "not enough CP") {
+            Some(skipped) => format!(
+                "it runs out of CP for `{}` on line {}",
+                skipped.name, skipped.line
+            ),
+            None => return None,
+        },
+    };
+    Some(problem)
+}
+
+// Check every task's macro finishes its recipe before any materials are
+// spent. Tasks whose gearset has no configured stats can't be checked.
+pub fn preflight(tasks: &[Task]) -> Result<(), Error> {
+    let mut problems = vec![];
+    for task in tasks {
+        let outcome = match simulate_task(task) {
+            Some(outcome) => outcome,
+            None => {
+                log::warn!(
+                    "no gearset stats or recipe data for `{}`, skipping its pre-flight check",
+                    task.item.name
+                );
+                continue;
+            }
+        };
+        log::info!("simulated `{}`: {}", task.item.name, outcome);
+        if let Some(problem) = outcome_problem(&outcome, task) {
+            problems.push(format!("`{}`: {}", task.item.name, problem));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(failure::format_err!(
+            "refusing to craft, these macros would not complete their recipes:\n{}",
+            problems.join("\n")
+        ))
+    }
+}
+
+// Plan out the tasks without touching the game, refusing the same macros
+// with too many role actions as crafting would. Every input is captured by a
+// recording backend instead, then printed task by task along with how long
+// the waits in it add up to.
+pub fn dry_run(tasks: &[Task], mode: ActionMode, saved: &SavedSlots) -> Result<(), Error> {
+    check_role_actions(tasks)?;
+    let backend = ui::RecordingBackend::new();
+    let window = ui::WinHandle::new(backend.clone());
+    let mut start = 0;
+    let mut total = Duration::default();
+    let plan = role_actions::plan(&role_action_stages(tasks), saved);
+    run_tasks(
+        &window,
+        tasks,
+        mode,
+        &plan,
+        |_| {},
+        |i| {
+            let inputs = backend.inputs();
+            let planned = &inputs[start..];
+            let duration = ui::total_wait(planned);
+            let task = &tasks[i];
+            println!(
+                "task {}/{}: {}x {} (gearset {}, {} actions)",
+                i + 1,
+                tasks.len(),
+                task.count,
+                task.item.name,
+                task.gearset,
+                task.actions().count()
+            );
+            print!("{}", ui::render_inputs(planned));
+            let mut crystals = vec![];
+            crystal::add(&mut crystals, &task.item.recipe.crystals, task.count);
+            println!("crystals: {}", crystal::describe(&crystals));
+            match simulate_task(task) {
+                Some(outcome) => println!("simulation: {}", outcome),
+                None => println!("simulation: no gearset stats or recipe data"),
+            }
+            println!("estimated duration: {}\n", format_duration(duration));
+            start = inputs.len();
+            total += duration;
+        },
+    );
+    println!("estimated total duration: {}", format_duration(total));
+    println!(
+        "crystals needed: {}",
+        crystal::describe(&batch_crystals(tasks))
+    );
+    println!(
+        "role actions: {} /aaction command(s), {} fewer than an LRU cache",
+        plan.count(),
+        plan.saved()
+    );
+    Ok(())
+}
+
+// Every shard, crystal and cluster a batch of tasks will use up
+pub fn batch_crystals(tasks: &[Task]) -> Vec<(Crystal, u64)> {
+    let mut totals = vec![];
+    for task in tasks {
+        crystal::add(&mut totals, &task.item.recipe.crystals, task.count);
+    }
+    totals
+}
+
+// Does the actual work for craft_items, calling `after_role_actions` with the
+// index of each task once its role actions are set up, and `after_task`
+// once it's been run.
+fn run_tasks<G: FnMut(usize), F: FnMut(usize)>(
+    window: &ui::WinHandle,
+    tasks: &[Task],
+    mode: ActionMode,
+    plan: &Plan,
+    mut after_role_actions: G,
+    mut after_task: F,
+) {
     // TODO: Investigate why there's always a longer delay after Careful Synthesis II
     // TODO: Tea is going to be a problem for non-specialty recipes
-    let mut role_actions = RoleActions::new(window);
-    // Clear role actions before we iterate tasks so the game state
-    // and role action state will be in sync.
-    aaction_clear(window);
     let mut gearset: u64 = 0;
-    for task in tasks {
+    for (i, task) in tasks.iter().enumerate() {
         // Change to the appropriate job if one is set. XIV
         // gearsets start at 1, so 0 is a safe empty value.
         if task.gearset > 0 && task.gearset != gearset {
-            // If we're changing jobs we need to set role actions up again,
-            // otherwise there's a good chance we can reuse some of the role
-            // actions we already have for the next craft
-            aaction_clear(window);
-            ui::wait_ms(200);
+            ui::wait_ms(window, ui::timing().gearset_change);
             change_gearset(window, task.gearset);
             gearset = task.gearset;
         }
@@ -33,28 +214,40 @@ pub fn craft_items(window: ui::WinHandle, tasks: &[Task]) {
             toggle_collectable(window);
         }
 
-        // Check the role action cache and configure any we need for this task
-        configure_role_actions(&mut role_actions, task);
+        // Swap in any role actions this task needs that aren't slotted yet.
+        // Every job has its own slots, so they're planned per job.
+        apply_role_actions(window, &plan.commands[i]);
+        after_role_actions(i);
 
         // Bring up the crafting window itself and give it time to appear
         ui::open_craft_window(window);
-        ui::wait_secs(1);
+        ui::wait_ms(window, ui::timing().craft_log_open);
 
         // Navigate to the correct recipe based on the index provided
-        select_recipe(window, &task);
+        select_recipe(window, task);
         // Time to craft the items
-        execute_task(window, &task);
+        execute_task(window, task, mode);
 
         // Close out of the cvrafting window and stand up
         clear_windows(window);
-        ui::wait_secs(2);
+        ui::wait_ms(window, ui::timing().task_end);
         if task.collectable {
             toggle_collectable(window);
         }
+        after_task(i);
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
     }
 }
 
-fn clear_windows(window: ui::WinHandle) {
+fn clear_windows(window: &ui::WinHandle) {
     println!("clearing window...");
     // Hitting escape closes one window each. 10 is excessive, but conservative
     for _ in 0..2 {
@@ -64,23 +257,51 @@ fn clear_windows(window: ui::WinHandle) {
     // Cancelling twice will close the System menu if it is open
     ui::cancel(window);
     ui::cancel(window);
-    ui::wait_secs(1);
+    ui::wait_ms(window, ui::timing().close_windows);
     ui::enter(window);
     ui::enter(window);
 }
 
-fn configure_role_actions(role_actions: &mut RoleActions, task: &Task) {
-    for action in &task.actions {
-        if role_actions.is_role_action(&action.name) {
-            role_actions.add_action(&action.name);
-            ui::wait_ms(250); // In testing, the game takes 1 second per role action
+// The role actions each task needs, on the job its recipe is for. Tasks that
+// don't switch gearsets run on whatever job is active, so their job isn't
+// known when the role actions are slotted.
+pub fn role_action_stages(tasks: &[Task]) -> Vec<Stage> {
+    let role_actions = RoleActions::new();
+    tasks
+        .iter()
+        .map(|task| Stage {
+            job: match task.gearset {
+                0 => None,
+                _ => task.item.recipe.job,
+            },
+            actions: task
+                .actions()
+                .filter(|action| role_actions.is_role_action(&action.name, task.item.recipe.job))
+                .map(|action| action.name.clone())
+                .collect(),
+        })
+        .collect()
+}
+
+fn apply_role_actions(window: &ui::WinHandle, commands: &[Command]) {
+    for command in commands {
+        log::debug!("{}", command);
+        match command {
+            Command::Clear => {
+                aaction_clear(window);
+                continue;
+            }
+            Command::Add(action) => aaction_add(window, action),
+            Command::Remove(action) => aaction_remove(window, action),
         }
+        // In testing, the game takes 1 second per role action
+        ui::wait_ms(window, ui::timing().role_action);
     }
 }
 
 // Selects the appropriate recipe then leaves the cursor on the Synthesize
 // button, ready for material selection.
-fn select_recipe(window: ui::WinHandle, task: &Task) {
+fn select_recipe(window: &ui::WinHandle, task: &Task) {
     log::info!("selecting recipe...");
     // Loop backward through the UI 9 times to ensure we hit the text box
     // no matter what crafting class we are. The text input boxes are strangely
@@ -94,11 +315,11 @@ fn select_recipe(window: ui::WinHandle, task: &Task) {
 
     ui::confirm(window);
     send_string(window, &task.item.name);
-    ui::wait_ms(200);
+    ui::wait_ms(window, ui::timing().recipe_search);
     ui::enter(window);
 
     // It takse up to a second for results to populate
-    ui::wait_secs(1);
+    ui::wait_ms(window, ui::timing().search_results);
 
     // Navigate to the offset we need
     for _ in 0..task.index {
@@ -109,18 +330,24 @@ fn select_recipe(window: ui::WinHandle, task: &Task) {
     ui::confirm(window);
 }
 
-fn select_materials(window: ui::WinHandle, task: &Task) {
-    log::info!("selecting materials...");
+fn select_materials(window: &ui::WinHandle, task: &Task) {
+    log::info!("selecting materials ({})...", task.hq);
+    let presses: Vec<Presses> = task
+        .item
+        .materials
+        .iter()
+        .map(|material| task.hq.presses(material))
+        .collect();
     ui::cursor_up(window);
-    // TODO implement HQ > NQ
     ui::cursor_right(window);
     ui::cursor_right(window);
 
-    // The cursor should be on the quantity field of the bottom item now
-    // We move through the ingredients backwards because we start at the bottom of t
+    // The cursor should be on the HQ quantity field of the bottom item now.
+    // We move through the ingredients backwards because we start at the
+    // bottom of the list.
     for (i, material) in task.item.materials.iter().enumerate().rev() {
         log::trace!("{}x {}", material.count, material.name);
-        for _ in 0..material.count {
+        for _ in 0..presses[i].hq_before {
             ui::confirm(window)
         }
         // Don't move up if we've made it back to the top of the ingredients
@@ -128,26 +355,35 @@ fn select_materials(window: ui::WinHandle, task: &Task) {
             ui::cursor_up(window);
         }
     }
+    // Then back down the NQ column, topping up with HQ on the way for
+    // strategies that want HQ to win
     ui::cursor_left(window);
-    for material in &task.item.materials {
-        for _ in 0..material.count {
+    for p in &presses {
+        for _ in 0..p.nq {
             ui::confirm(window)
         }
+        if p.hq_after > 0 {
+            ui::cursor_right(window);
+            for _ in 0..p.hq_after {
+                ui::confirm(window)
+            }
+            ui::cursor_left(window);
+        }
         ui::cursor_down(window);
     }
 }
 
-fn execute_task(window: ui::WinHandle, task: &Task) {
+fn execute_task(window: &ui::WinHandle, task: &Task, mode: ActionMode) {
     for task_index in 1..=task.count {
         println!("crafting {} {}/{}", task.item.name, task_index, task.count);
         // If we're at the start of a task we will already have the Synthesize button
         // selected with the pointer.
-        select_materials(window, &task);
+        select_materials(window, task);
         ui::confirm(window);
         // Wait for the craft dialog to pop up
-        ui::wait_secs(2);
+        ui::wait_ms(window, ui::timing().synthesis_start);
         // and now execute the actions
-        execute_actions(window, &task.actions);
+        execute_steps(window, &task.steps, mode);
 
         // There are two paths here. If an item is collectable then it will
         // prompt a dialog to collect the item as collectable. In this case,
@@ -158,64 +394,169 @@ fn execute_task(window: ui::WinHandle, task: &Task) {
         // At the end of this sequence the cursor should have selected the recipe
         // again and be on the Synthesize button.
         if task.collectable {
-            ui::wait_secs(1);
+            ui::wait_ms(window, ui::timing().collectable_prompt);
             ui::confirm(window);
             // Give the UI a moment
-            ui::wait_secs(3);
+            ui::wait_ms(window, ui::timing().collectable_confirm);
             ui::confirm(window)
         } else {
-            ui::wait_secs(4);
+            ui::wait_ms(window, ui::timing().synthesis_end);
             ui::confirm(window);
         }
     }
 }
 
-fn execute_actions(window: ui::WinHandle, actions: &[macros::Action]) {
-    for action in actions {
-        // Each character has a 20ms wait and the shortest action string
-        // we can make (observe or reclaim) is 240 ms, along with 50ms
-        // from send_action. That reduces how much time is needed to wait
-        // here for the GCD to finish. Although macros always wait in 2 or
-        // 3 second periods, the actual wait period is 2.0 and 2.5 seconds,
-        // so that's adjusted here.
-        send_action(window, &action.name);
+fn execute_steps(window: &ui::WinHandle, steps: &[macros::Entry], mode: ActionMode) {
+    let timing = ui::timing();
+    for step in steps {
+        let action = match step {
+            macros::Entry::Action(action) => action,
+            macros::Entry::Wait { seconds, .. } => {
+                ui::wait_ms(window, seconds * 1000);
+                continue;
+            }
+            // Echoes are only for the player, so they go to the log instead,
+            // but their waits still hold up the macro
+            macros::Entry::Echo {
+                text, sound, wait, ..
+            } => {
+                match sound {
+                    Some(sound) => log::info!("echo: {} <se.{}>", text, sound),
+                    None => log::info!("echo: {}", text),
+                }
+                if *wait > 0 {
+                    ui::wait_ms(window, wait * 1000);
+                }
+                continue;
+            }
+        };
+        let key = match mode {
+            ActionMode::Hotbar => ui::keybinds().action(&action.name),
+            ActionMode::Typed => None,
+        };
+        // Although macros always wait in 2 or 3 second periods, the actual
+        // GCD is 2.0 and 2.5 seconds. The timing profile accounts for that
+        // along with the time taken to send the action itself.
+        let (short, long) = match key {
+            Some(key) => {
+                log::debug!("action(`{}`) -> {}", action.name, key);
+                ui::send_key(window, key);
+                (timing.hotbar_action_short, timing.hotbar_action_long)
+            }
+            None => {
+                send_action(window, &action.name);
+                (timing.typed_action_short, timing.typed_action_long)
+            }
+        };
+
+        // Actions off the GCD can be followed up immediately. Anything not
+        // in the action database is assumed to be on it.
+        if !action.info().is_none_or(|info| info.gcd) {
+            continue;
+        }
         if action.wait == 2 {
-            ui::wait_ms(1700);
+            ui::wait_ms(window, short);
         } else {
-            ui::wait_ms(2200);
-        };
+            ui::wait_ms(window, long);
+        }
+    }
+}
+
+// Returns the actions used by the tasks which have no crafting keybind.
+// In hotbar mode these fall back to being typed out.
+pub fn unbound_actions(tasks: &[Task], keybinds: &Keybinds) -> BTreeSet<String> {
+    tasks
+        .iter()
+        .flat_map(|task| task.actions())
+        .filter(|action| keybinds.action(&action.name).is_none())
+        .map(|action| action.name.clone())
+        .collect()
+}
+
+// Check no task's macro needs more role actions than the game has slots
+// for. Otherwise one would be evicted partway through the macro and the
+// craft would fail mid-rotation.
+pub fn check_role_actions(tasks: &[Task]) -> Result<(), Error> {
+    let mut problems = vec![];
+    for (task, stage) in tasks.iter().zip(role_action_stages(tasks)) {
+        let overflow = role_actions::overflow(&stage.actions);
+        if !overflow.is_empty() {
+            problems.push(format!(
+                "`{}`: the macro needs {} role actions but only {} fit, no slot for `{}`",
+                task.item.name,
+                role_actions::SLOTS + overflow.len(),
+                role_actions::SLOTS,
+                overflow.join("`, `")
+            ));
+        }
     }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(failure::format_err!(
+            "refusing to craft, these macros need too many role actions:\n{}",
+            problems.join("\n")
+        ))
+    }
+}
+
+// Verify the tasks can run in the requested mode. Strict hotbar mode
+// refuses to start if any action would need to fall back to typing.
+pub fn check_action_mode(
+    tasks: &[Task],
+    mode: ActionMode,
+    strict: bool,
+    keybinds: &Keybinds,
+) -> Result<(), Error> {
+    if mode != ActionMode::Hotbar {
+        return Ok(());
+    }
+
+    let unbound = unbound_actions(tasks, keybinds);
+    if unbound.is_empty() {
+        return Ok(());
+    }
+    let names = unbound.into_iter().collect::<Vec<_>>().join("`, `");
+    if strict {
+        return Err(failure::format_err!(
+            "no crafting keybinds for: `{}`. Add them to [crafting_keybinds] or run without --strict",
+            names
+        ));
+    }
+    log::warn!("no crafting keybinds for `{}`, these will be typed", names);
+    Ok(())
 }
 
-fn send_string(window: ui::WinHandle, s: &str) {
+fn send_string(window: &ui::WinHandle, s: &str) {
     log::trace!("string(`{}`)", s);
     for c in s.chars() {
         ui::send_char(window, c);
     }
 }
 
-fn send_action(window: ui::WinHandle, action: &str) {
+fn send_action(window: &ui::WinHandle, action: &str) {
     log::debug!("action(`{}`)", action);
     ui::enter(window);
     send_string(window, &format!("/ac \"{}\"", action));
-    ui::wait_ms(50);
+    ui::wait_ms(window, ui::timing().chat_submit);
     ui::enter(window);
 }
 
-fn change_gearset(window: ui::WinHandle, gearset: u64) {
+fn change_gearset(window: &ui::WinHandle, gearset: u64) {
     log::debug!("gearset({})", gearset);
     println!("changing to gearset {}", gearset);
     ui::enter(window);
     send_string(window, &format!("/gearset change {}", gearset));
-    ui::wait_ms(50);
+    ui::wait_ms(window, ui::timing().chat_submit);
     ui::enter(window);
 }
 
-fn toggle_collectable(window: ui::WinHandle) {
-    send_action(window, &"collectable synthesis");
+fn toggle_collectable(window: &ui::WinHandle) {
+    send_action(window, "collectable synthesis");
 }
 
-pub fn aaction(window: ui::WinHandle, verb: &str, action: &str) {
+pub fn aaction(window: &ui::WinHandle, verb: &str, action: &str) {
     ui::enter(window);
     if verb == "clear" {
         send_string(window, "/aaction clear");
@@ -223,17 +564,260 @@ pub fn aaction(window: ui::WinHandle, verb: &str, action: &str) {
         send_string(window, &format!("/aaction \"{}\" {}", action, verb));
     }
     ui::enter(window);
-    //ui::wait_secs(1);
+    //ui::wait_ms(window, 1000);
 }
 
-pub fn aaction_clear(window: ui::WinHandle) {
+pub fn aaction_clear(window: &ui::WinHandle) {
     aaction(window, "clear", "")
 }
 
-pub fn aaction_add(window: ui::WinHandle, action: &str) {
+pub fn aaction_add(window: &ui::WinHandle, action: &str) {
     aaction(window, "on", action)
 }
 
-pub fn aaction_remove(window: ui::WinHandle, action: &str) {
+pub fn aaction_remove(window: &ui::WinHandle, action: &str) {
     aaction(window, "off", action)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::garland::{Item, Material, Recipe};
+    use crate::hq::HqStrategy;
+    use crate::job::Job;
+    use crate::ui::{assert_golden, RecordingBackend, WinHandle};
+    use std::collections::HashMap;
+
+    fn test_task() -> Task {
+        Task {
+            item: Item {
+                id: 19_881,
+                name: "Crimson Cider".to_string(),
+                materials: vec![
+                    Material {
+                        id: 19_872,
+                        name: "Crimson Pepper".to_string(),
+                        count: 1,
+                        craftable: false,
+                    },
+                    Material {
+                        id: 19_907,
+                        name: "Loquat".to_string(),
+                        count: 3,
+                        craftable: false,
+                    },
+                ],
+                recipe: Recipe {
+                    id: 3_374,
+                    job: Some(Job::CUL),
+                    level: 70,
+                    rlvl: 290,
+                    stars: 0,
+                    progress: 400,
+                    quality: 5000,
+                    durability: 40,
+                    yield_count: 1,
+                    crystals: vec![
+                        (Crystal::from_id(8).unwrap(), 4),
+                        (Crystal::from_id(13).unwrap(), 4),
+                    ],
+                },
+            },
+            count: 2,
+            index: 1,
+            steps: macros::parse_file("src/test_macro".into()).unwrap(),
+            gearset: 8,
+            collectable: false,
+            crafter: Some(sim::Crafter {
+                level: 290,
+                craftsmanship: 1500,
+                control: 1450,
+                cp: 500,
+            }),
+            hq: HqStrategy::NqFirst,
+        }
+    }
+
+    fn recorder() -> (RecordingBackend, WinHandle) {
+        let backend = RecordingBackend::new();
+        (backend.clone(), WinHandle::new(backend))
+    }
+
+    #[test]
+    fn duration_formatting() {
+        assert_eq!(format_duration(Duration::from_millis(59_900)), "0m 59s");
+        assert_eq!(format_duration(Duration::from_secs(754)), "12m 34s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+    }
+
+    #[test]
+    fn crystals_for_batch() {
+        let mut other = test_task();
+        other.count = 5;
+        other.item.recipe.crystals = vec![(Crystal::from_id(16).unwrap(), 1)];
+        let crystals = batch_crystals(&[test_task(), other]);
+        assert_eq!(
+            crystal::describe(&crystals),
+            "8x Fire Crystal, 8x Water Crystal, 5x Wind Cluster"
+        );
+    }
+
+    #[test]
+    fn role_action_limit() {
+        let mut task = test_task();
+        assert!(check_role_actions(&[test_task()]).is_ok());
+
+        let names = [
+            "Innovation",
+            "Ingenuity II",
+            "Manipulation",
+            "Name of Fire",
+            "Waste Not II",
+            "Maker's Mark",
+            "Piece by Piece",
+            "Careful Synthesis II",
+            "Rumination",
+            // Culinarian actions don't need slots on a Culinarian recipe
+            "Steady Hand II",
+            "Reclaim",
+        ];
+        for (i, name) in names.iter().enumerate() {
+            task.steps.push(macros::Entry::Action(macros::Action {
+                name: name.to_string(),
+                wait: 2,
+                line: 100 + i,
+            }));
+        }
+        let tasks = [test_task(), task];
+        let err = check_role_actions(&tasks).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "refusing to craft, these macros need too many role actions:\n\
+             `Crimson Cider`: the macro needs 11 role actions but only 10 fit, no slot for `Rumination`"
+        );
+        // Planning can't slot them either, so a dry run refuses too
+        assert!(dry_run(&tasks, ActionMode::Typed, &SavedSlots::default()).is_err());
+    }
+
+    #[test]
+    fn preflight_checks() {
+        let mut task = test_task();
+        assert!(preflight(&[task]).is_ok());
+
+        task = test_task();
+        task.item.recipe.progress = 3000;
+        let err = preflight(&[task]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("`Crimson Cider`: the macro ends with only 475/3000 progress"));
+
+        task = test_task();
+        task.item.recipe.durability = 10;
+        let err = preflight(&[task]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("the craft breaks at step 5 (line 5) with 0/400 progress"));
+
+        task = test_task();
+        task.crafter.as_mut().unwrap().cp = 200;
+        let err = preflight(&[task]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("it runs out of CP for `Byregot's Blessing` on line 5"));
+
+        // Without stats there's nothing to check against
+        task = test_task();
+        task.crafter = None;
+        task.item.recipe.progress = 3000;
+        assert!(preflight(&[task]).is_ok());
+    }
+
+    #[test]
+    fn hotbar_strict_mode() {
+        let bound = [
+            "Inner Quiet",
+            "Great Strides",
+            "Manipulation II",
+            "Careful Synthesis III",
+        ];
+        let crafting: HashMap<String, String> = bound
+            .iter()
+            .zip(["c", "e", "t", "2"].iter())
+            .map(|(name, key)| (name.to_string(), key.to_string()))
+            .collect();
+        let keybinds = Keybinds::from_tables(&HashMap::new(), &crafting).unwrap();
+        let tasks = [test_task()];
+
+        let err = check_action_mode(&tasks, ActionMode::Hotbar, true, &keybinds).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no crafting keybinds for: `Byregot's Blessing`, `Comfort Zone`. \
+             Add them to [crafting_keybinds] or run without --strict"
+        );
+        assert!(check_action_mode(&tasks, ActionMode::Hotbar, false, &keybinds).is_ok());
+        assert!(check_action_mode(&tasks, ActionMode::Typed, true, &keybinds).is_ok());
+    }
+
+    #[test]
+    fn hotbar_falls_back_to_typing() {
+        // The tests never set any crafting keybinds, so every action is typed
+        assert!(ui::keybinds().crafting.is_empty());
+        let (typed, window) = recorder();
+        execute_steps(&window, &test_task().steps, ActionMode::Typed);
+        let (hotbar, window) = recorder();
+        execute_steps(&window, &test_task().steps, ActionMode::Hotbar);
+        assert_eq!(hotbar.render(), typed.render());
+        assert!(hotbar.render().contains(r#"type "/ac \"Comfort Zone\"""#));
+    }
+
+    #[test]
+    fn golden_select_recipe() {
+        let (backend, window) = recorder();
+        select_recipe(&window, &test_task());
+        assert_golden("select_recipe", &backend.render());
+    }
+
+    #[test]
+    fn golden_select_materials() {
+        let (backend, window) = recorder();
+        select_materials(&window, &test_task());
+        assert_golden("select_materials", &backend.render());
+    }
+
+    #[test]
+    fn golden_select_materials_hq() {
+        let (backend, window) = recorder();
+        let mut task = test_task();
+        task.hq = "Loquat=2".parse().unwrap();
+        select_materials(&window, &task);
+        assert_golden("select_materials_hq", &backend.render());
+    }
+
+    #[test]
+    fn golden_execute_task() {
+        let (backend, window) = recorder();
+        execute_task(&window, &test_task(), ActionMode::Typed);
+        assert_golden("execute_task", &backend.render());
+    }
+
+    #[test]
+    fn golden_execute_task_collectable() {
+        let (backend, window) = recorder();
+        let mut task = test_task();
+        task.collectable = true;
+        task.count = 1;
+        execute_task(&window, &task, ActionMode::Typed);
+        assert_golden("execute_task_collectable", &backend.render());
+    }
+
+    #[test]
+    fn golden_role_actions() {
+        let (backend, window) = recorder();
+        // Only what's missing from the saved slots is added
+        let mut saved = SavedSlots::default();
+        saved.set(Job::CUL, vec![]);
+        let plan = role_actions::plan(&role_action_stages(&[test_task()]), &saved);
+        apply_role_actions(&window, &plan.commands[0]);
+        assert_golden("role_actions", &backend.render());
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Element {
+    Fire,
+    Ice,
+    Wind,
+    Earth,
+    Lightning,
+    Water,
+}
+
+const ELEMENTS: [Element; 6] = [
+    Element::Fire,
+    Element::Ice,
+    Element::Wind,
+    Element::Earth,
+    Element::Lightning,
+    Element::Water,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Size {
+    Shard,
+    Crystal,
+    Cluster,
+}
+
+const SIZES: [Size; 3] = [Size::Shard, Size::Crystal, Size::Cluster];
+
+// A shard, crystal or cluster. Fields are ordered so sorting matches the
+// item ids, which is also the order the game lists them in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Crystal {
+    pub size: Size,
+    pub element: Element,
+}
+
+impl Crystal {
+    // Item ids run from the Fire Shard at 2 to the Water Cluster at 19, in
+    // element order for each size
+    pub fn from_id(id: u64) -> Option<Crystal> {
+        match id {
+            2..=19 => Some(Crystal {
+                size: SIZES[(id as usize - 2) / 6],
+                element: ELEMENTS[(id as usize - 2) % 6],
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> u64 {
+        2 + self.size as u64 * 6 + self.element as u64
+    }
+}
+
+impl fmt::Display for Crystal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {:?}", self.element, self.size)
+    }
+}
+
+// Add `crafts` times the crystals of a recipe to a running total, keeping
+// it in game order
+pub fn add(totals: &mut Vec<(Crystal, u64)>, crystals: &[(Crystal, u64)], crafts: u64) {
+    for &(crystal, count) in crystals {
+        match totals.iter_mut().find(|(c, _)| *c == crystal) {
+            Some((_, total)) => *total += count * crafts,
+            None => totals.push((crystal, count * crafts)),
+        }
+    }
+    totals.sort();
+}
+
+// List crystals for printing, e.g. `4x Fire Crystal, 4x Water Crystal`
+pub fn describe(crystals: &[(Crystal, u64)]) -> String {
+    if crystals.is_empty() {
+        return "none".to_string();
+    }
+    let counts: Vec<String> = crystals
+        .iter()
+        .map(|(crystal, count)| format!("{}x {}", count, crystal))
+        .collect();
+    counts.join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crystal_ids() {
+        let shard = Crystal::from_id(2).unwrap();
+        assert_eq!(shard.to_string(), "Fire Shard");
+        let cluster = Crystal::from_id(16).unwrap();
+        assert_eq!(cluster.to_string(), "Wind Cluster");
+        assert_eq!(Crystal::from_id(13).unwrap().to_string(), "Water Crystal");
+        assert_eq!(Crystal::from_id(1), None);
+        assert_eq!(Crystal::from_id(20), None);
+        for id in 2..=19 {
+            assert_eq!(Crystal::from_id(id).unwrap().id(), id);
+        }
+
+        let mut totals = vec![];
+        add(&mut totals, &[(cluster, 2)], 3);
+        add(&mut totals, &[(shard, 1), (cluster, 1)], 2);
+        assert_eq!(totals, vec![(shard, 2), (cluster, 8)]);
+        assert_eq!(describe(&totals), "2x Fire Shard, 8x Wind Cluster");
+        assert_eq!(describe(&[]), "none");
+    }
+}
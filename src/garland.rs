@@ -1,24 +1,26 @@
+use crate::cache::Cache;
+use crate::crystal::{self, Crystal};
+use crate::job::Job;
+use crate::provider::ItemProvider;
+use crate::sim;
 use failure::Error;
-use log;
-use reqwest;
-use serde_json;
 use std::fmt;
 use url::form_urlencoded;
 
 impl fmt::Display for JsonItem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "item {{");
-        writeln!(f, "\tname: {}", self.item.name);
-        write!(f, "\tid:   {}", self.item.id);
-        write!(f, "\tingredients; {{");
+        writeln!(f, "item {{")?;
+        writeln!(f, "\tname: {}", self.item.name)?;
+        write!(f, "\tid:   {}", self.item.id)?;
+        write!(f, "\tingredients; {{")?;
         for (i, elem) in self.ingredients.iter().enumerate() {
             writeln!(
                 f,
                 "\t\t {}x {} (id: {})",
                 self.item.craft[0].ingredients[i].amount, elem.name, elem.id
-            );
+            )?;
         }
-        writeln!(f, "\t}}");
+        writeln!(f, "\t}}")?;
         writeln!(f, "}}")
     }
 }
@@ -45,16 +47,35 @@ struct JsonItemData {
 struct JsonItemIngredient {
     id: u64,
     name: String,
+    // Only present when the ingredient can be crafted itself
+    #[serde(default)]
+    craft: Option<serde_json::Value>,
 }
 
 #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
 struct JsonCraft {
+    #[serde(default)]
+    id: u64,
     job: u64,
+    #[serde(default)]
+    lvl: u64,
+    #[serde(default)]
+    rlvl: u64,
+    #[serde(default)]
+    stars: u64,
+    #[serde(default = "default_yield", rename = "yield")]
+    yield_count: u64,
     quality: u64,
     progress: u64,
+    #[serde(default)]
+    durability: u64,
     ingredients: Vec<JsonCraftIngredient>,
 }
 
+fn default_yield() -> u64 {
+    1
+}
+
 #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
 struct JsonCraftIngredient {
     id: u64,
@@ -64,47 +85,140 @@ struct JsonCraftIngredient {
 
 #[derive(Debug)]
 pub struct Item {
+    pub id: u64,
     pub name: String,
     pub materials: Vec<Material>,
+    pub recipe: Recipe,
+}
+
+// Everything about the recipe used to craft an item. Fields GarlandTools
+// leaves out are 0, apart from the yield which defaults to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recipe {
+    pub id: u64,
+    pub job: Option<Job>, // None for jobs Talan doesn't know about
+    pub level: u32,       // job level needed to craft it
+    pub rlvl: u32,        // recipe level, which sets its difficulty
+    pub stars: u32,
+    pub progress: u32,
+    pub quality: u32,
+    pub durability: u32,
+    pub yield_count: u32,              // items made per craft
+    pub crystals: Vec<(Crystal, u64)>, // kept apart from materials as they're never selected
+}
+
+impl Recipe {
+    // The recipe's parameters for the simulator, if GarlandTools had them
+    pub fn synthesis(&self) -> Option<sim::Recipe> {
+        if self.progress == 0 || self.durability == 0 {
+            return None;
+        }
+        Some(sim::Recipe {
+            level: self.rlvl,
+            progress: self.progress,
+            quality: self.quality,
+            durability: self.durability,
+        })
+    }
+}
+
+impl fmt::Display for Recipe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.job {
+            Some(job) => write!(f, "{}", job)?,
+            None => write!(f, "???")?,
+        }
+        write!(f, " lv{} (rlvl {}", self.level, self.rlvl)?;
+        if self.stars > 0 {
+            write!(f, ", {}", "★".repeat(self.stars as usize))?;
+        }
+        write!(
+            f,
+            "), progress {}, quality {}, durability {}, yield {}",
+            self.progress, self.quality, self.durability, self.yield_count
+        )
+    }
 }
 
 #[derive(Debug)]
 pub struct Material {
+    #[allow(dead_code)]
     pub id: u64,
     pub name: String,
     pub count: u64,
+    pub craftable: bool,
 }
 
 impl fmt::Display for Item {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "{}", self.name);
-        writeln!(f, "[");
+        writeln!(f, "{} (id: {})", self.name, self.id)?;
+        writeln!(f, "{}", self.recipe)?;
+        writeln!(f, "crystals: {}", crystal::describe(&self.recipe.crystals))?;
+        writeln!(f, "[")?;
         for m in &self.materials {
-            writeln!(f, "  {}x {}", m.count, m.name);
+            writeln!(f, "  {}x {}", m.count, m.name)?;
         }
         writeln!(f, "]")
     }
 }
 
 // Convert Garland's json layout to a structure easier to use
-// for Talan's purposes.
-impl From<JsonItem> for Item {
-    fn from(json_item: JsonItem) -> Self {
+// for Talan's purposes. Items can have a recipe for several jobs, so `job`
+// picks which one to use. It can be left out when there's only one recipe.
+impl Item {
+    fn from_json(json_item: JsonItem, job: Option<Job>) -> Result<Item, Error> {
+        let name = &json_item.item.name;
+        let crafts = &json_item.item.craft;
+        let matching: Vec<&JsonCraft> = crafts
+            .iter()
+            .filter(|craft| job.is_none() || Job::from_garland_id(craft.job) == job)
+            .collect();
+        let craft = match (matching.len(), job) {
+            (1, _) => matching[0],
+            (0, Some(job)) => {
+                return Err(failure::format_err!(
+                    "`{}` has no {} recipe, it is crafted by {}",
+                    name,
+                    job,
+                    craft_jobs(crafts)
+                ))
+            }
+            (0, None) => return Err(failure::format_err!("`{}` has no recipe", name)),
+            (_, Some(job)) => {
+                return Err(failure::format_err!(
+                    "`{}` has {} {} recipes and Talan can't tell which to use",
+                    name,
+                    matching.len(),
+                    job
+                ))
+            }
+            (_, None) => {
+                return Err(failure::format_err!(
+                    "`{}` is crafted by {}, set a job for the task to pick one",
+                    name,
+                    craft_jobs(crafts)
+                ))
+            }
+        };
+
         // The JSON layout keeps terse info like id/amount in the
         // craft ingredients, but keeps all the information about
         // each of those in the top level. The data is all extracted
         // and combined in this conversion method.
         let mut v = Vec::new();
-        for craft_item in &json_item.item.craft[0].ingredients {
-            // Ignore shards, crystals, and clusters
-            if craft_item.id <= 19 {
+        let mut crystals = Vec::new();
+        for craft_item in &craft.ingredients {
+            if let Some(crystal) = Crystal::from_id(craft_item.id) {
+                crystals.push((crystal, craft_item.amount));
                 continue;
             }
 
             let mut name = String::new();
+            let mut craftable = false;
             for ingredient in &json_item.ingredients {
                 if craft_item.id == ingredient.id {
                     name = ingredient.name.clone();
+                    craftable = ingredient.craft.is_some();
                 }
             }
 
@@ -112,64 +226,220 @@ impl From<JsonItem> for Item {
                 id: craft_item.id,
                 count: craft_item.amount,
                 name: name.to_string(),
+                craftable,
             });
         }
 
-        Item {
-            name: json_item.item.name,
+        let recipe = Recipe {
+            id: craft.id,
+            job: Job::from_garland_id(craft.job),
+            level: craft.lvl as u32,
+            rlvl: craft.rlvl as u32,
+            stars: craft.stars as u32,
+            progress: craft.progress as u32,
+            quality: craft.quality as u32,
+            durability: craft.durability as u32,
+            yield_count: craft.yield_count as u32,
+            crystals,
+        };
+
+        Ok(Item {
+            id: json_item.item.id,
+            name: json_item.item.name.clone(),
             materials: v,
-        }
+            recipe,
+        })
     }
 }
 
-// Return the item id for the provided item name
-pub fn query_item_id(item_name: &str) -> Result<Option<u64>, Error> {
-    let garland_search_url = String::from("https://www.garlandtools.org/api/search.php?");
-    let encoded_url: String = form_urlencoded::Serializer::new(garland_search_url)
-        .append_pair("craftable", "1")
-        .append_pair("type", "item")
-        .append_pair("text", item_name)
-        .append_pair("lang", "en")
-        .append_pair("exact", "1")
-        .finish();
-    log::trace!("fetch({})", encoded_url);
-    let body = reqwest::get(&encoded_url)?.text()?;
-    let items: Vec<JsonItemSearchResult> = serde_json::from_str(&body)?;
-    // We should not get duplicates, but use just the first if we do
-    println!("items: {:?}", items);
-    if items.is_empty() {
-        return Err(failure::format_err!("item `{}` not found", item_name));
+// List the jobs with a recipe, for error messages
+fn craft_jobs(crafts: &[JsonCraft]) -> String {
+    let jobs: Vec<String> = crafts
+        .iter()
+        .map(|craft| match Job::from_garland_id(craft.job) {
+            Some(job) => job.to_string(),
+            None => format!("job {}", craft.job),
+        })
+        .collect();
+    jobs.join(", ")
+}
+
+// Fetch a GarlandTools API response as JSON
+fn fetch_json(url: &str) -> Result<serde_json::Value, Error> {
+    log::trace!("fetch({})", url);
+    let body = reqwest::get(url)?.text()?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+// Turn an item document, as served by GarlandTools, into an Item using the
+// recipe for `job`
+pub fn parse_item(data: serde_json::Value, job: Option<Job>) -> Result<Item, Error> {
+    let item: JsonItem = serde_json::from_value(data)?;
+    Item::from_json(item, job)
+}
+
+// Item data straight from the GarlandTools API, through the on-disk cache
+#[derive(Debug)]
+pub struct GarlandTools {
+    cache: Cache,
+}
+
+impl GarlandTools {
+    pub fn new(cache: Cache) -> GarlandTools {
+        GarlandTools { cache }
     }
-    let id: u64 = items[0].id.parse()?;
-    Ok(Some(id))
 }
 
-// Get the materials and other information for a given item
-pub fn fetch_item_info(name: &str) -> Result<Item, Error> {
-    let id = query_item_id(&name)?.unwrap();
-    let garland_item_url = String::from("http://www.garlandtools.org/db/doc/item/en/3/");
-    let encoded_url = format!("{}{}.json", garland_item_url, id);
-    log::trace!("fetch({})", encoded_url);
-    let body = reqwest::get(&encoded_url)?.text()?;
-    let item: JsonItem = serde_json::from_str(&body)?;
+impl ItemProvider for GarlandTools {
+    // Return the item id for the provided item name
+    fn item_id(&self, item_name: &str) -> Result<u64, Error> {
+        let garland_search_url = String::from("https://www.garlandtools.org/api/search.php?");
+        let encoded_url: String = form_urlencoded::Serializer::new(garland_search_url)
+            .append_pair("craftable", "1")
+            .append_pair("type", "item")
+            .append_pair("text", item_name)
+            .append_pair("lang", "en")
+            .append_pair("exact", "1")
+            .finish();
+        let data = self
+            .cache
+            .get_or_fetch("search", item_name, || fetch_json(&encoded_url))?;
+        let items: Vec<JsonItemSearchResult> = serde_json::from_value(data)?;
+        // We should not get duplicates, but use just the first if we do
+        log::debug!("items: {:?}", items);
+        if items.is_empty() {
+            return Err(failure::format_err!("item `{}` not found", item_name));
+        }
+        let id: u64 = items[0].id.parse()?;
+        Ok(id)
+    }
 
-    Ok(Item::from(item))
+    // Get the materials and other information for a given item, using the
+    // recipe for `job` if it has more than one.
+    fn item(&self, name: &str, job: Option<Job>) -> Result<Item, Error> {
+        let id = self.item_id(name)?;
+        let garland_item_url = String::from("http://www.garlandtools.org/db/doc/item/en/3/");
+        let encoded_url = format!("{}{}.json", garland_item_url, id);
+        let data = self
+            .cache
+            .get_or_fetch("item", &id.to_string(), || fetch_json(&encoded_url))?;
+        parse_item(data, job)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::cache::CacheMode;
+    use std::time::Duration;
+
+    // GarlandTools responses captured in the fixture cache, so no requests
+    // are ever made
+    fn fixtures() -> GarlandTools {
+        GarlandTools::new(Cache::new(
+            "src/test_cache",
+            Duration::from_secs(0),
+            CacheMode::Offline,
+        ))
+    }
+
+    #[test]
+    fn item_from_json() {
+        let json = r#"{
+            "item": {
+                "name": "Crimson Cider",
+                "id": 19881,
+                "craft": [{
+                    "id": 3374, "job": 15, "lvl": 70, "rlvl": 290, "yield": 3,
+                    "progress": 2100, "quality": 9800, "durability": 70,
+                    "ingredients": [
+                        {"id": 13, "amount": 2},
+                        {"id": 19872, "amount": 1},
+                        {"id": 19907, "amount": 3}
+                    ]
+                }]
+            },
+            "ingredients": [
+                {"id": 13, "name": "Water Crystal"},
+                {"id": 19872, "name": "Crimson Pepper"},
+                {"id": 19907, "name": "Loquat"}
+            ]
+        }"#;
+        let item = Item::from_json(serde_json::from_str(json).unwrap(), None).unwrap();
+        assert_eq!(item.id, 19_881);
+        assert_eq!(item.materials.len(), 2);
+        assert_eq!(
+            item.recipe,
+            Recipe {
+                id: 3_374,
+                job: Some(Job::CUL),
+                level: 70,
+                rlvl: 290,
+                stars: 0,
+                progress: 2100,
+                quality: 9800,
+                durability: 70,
+                yield_count: 3,
+                crystals: vec![(Crystal::from_id(13).unwrap(), 2)],
+            }
+        );
+        assert_eq!(
+            item.recipe.to_string(),
+            "CUL lv70 (rlvl 290), progress 2100, quality 9800, durability 70, yield 3"
+        );
+        assert_eq!(item.recipe.synthesis().unwrap().level, 290);
+    }
+
+    #[test]
+    fn item_recipe_by_job() {
+        let json = r#"{
+            "item": {
+                "name": "Rakshasa Axe",
+                "id": 23000,
+                "craft": [
+                    {"id": 1, "job": 9, "progress": 100, "quality": 100,
+                     "ingredients": [{"id": 100, "amount": 1}]},
+                    {"id": 2, "job": 11, "progress": 100, "quality": 100,
+                     "ingredients": [{"id": 200, "amount": 2}]}
+                ]
+            },
+            "ingredients": [
+                {"id": 100, "name": "Ingot"},
+                {"id": 200, "name": "Nugget"}
+            ]
+        }"#;
+        let parse = |job| Item::from_json(serde_json::from_str(json).unwrap(), job);
+
+        let item = parse(Some(Job::GSM)).unwrap();
+        assert_eq!(item.recipe.id, 2);
+        assert_eq!(item.materials[0].name, "Nugget");
+        assert_eq!(parse(Some(Job::BSM)).unwrap().materials[0].name, "Ingot");
+
+        let err = parse(None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`Rakshasa Axe` is crafted by BSM, GSM, set a job for the task to pick one"
+        );
+        let err = parse(Some(Job::CUL)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`Rakshasa Axe` has no CUL recipe, it is crafted by BSM, GSM"
+        );
+    }
+
     #[test]
     fn query_rakshasa_dogi_of_casting() {
         const RAKSHASA_DOGI_OF_CASTING_ID: u64 = 23821;
-        let id = query_item_id(&"Rakshasa Dogi of Casting").unwrap().unwrap();
+        let id = fixtures().item_id("Rakshasa Dogi of Casting").unwrap();
         assert_eq!(id, RAKSHASA_DOGI_OF_CASTING_ID);
     }
 
     #[test]
     fn query_crimson_cider_recipe() {
-        let item = fetch_item_info("Crimson Cider").unwrap();
+        let item = fixtures().item("Crimson Cider", None).unwrap();
         assert_eq!(item.name, "Crimson Cider");
+        assert_eq!(item.id, 19_881);
+        assert_eq!(item.recipe.job, Some(Job::CUL));
         assert_eq!(item.materials[0].name, "Crimson Pepper");
         assert_eq!(item.materials[0].count, 1);
         assert_eq!(item.materials[1].name, "Jhammel Ginger");
@@ -180,6 +450,10 @@ mod test {
         assert_eq!(item.materials[3].count, 1);
         assert_eq!(item.materials[4].name, "Loquat");
         assert_eq!(item.materials[4].count, 3);
+        assert_eq!(
+            crystal::describe(&item.recipe.crystals),
+            "4x Fire Crystal, 4x Water Crystal"
+        );
     }
 
     // This test verifies whether we receive the right information
@@ -193,7 +467,7 @@ mod test {
     // 'Sui-no-Sato Special Component'
     #[test]
     fn query_turnin_item() {
-        let item = fetch_item_info("Sui-no-Sato Special").unwrap();
+        let item = fixtures().item("Sui-no-Sato Special", None).unwrap();
         println!("{}", item);
         assert_eq!(item.name, "Sui-no-Sato Special");
         assert_eq!(item.materials.len(), 1);
@@ -0,0 +1,251 @@
+use crate::garland::{Item, Material};
+use failure::Error;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+// How a task picks between NQ and HQ copies of its materials. Once a row of
+// the material list is full the game swaps out the other quality, so the
+// column pressed last for a row is the one that takes priority.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum HqStrategy {
+    // NQ where possible, HQ for the rest
+    #[default]
+    NqFirst,
+    // HQ where possible, NQ for the rest
+    HqFirst,
+    // Exactly this many HQ of the named materials, NQ otherwise
+    Counts(BTreeMap<String, u64>),
+    // HQ first for the named materials, never HQ for the rest
+    Only(Vec<String>),
+}
+
+// The confirm presses for one row of the material list, in the order
+// they're made
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Presses {
+    pub hq_before: u64, // on the HQ column, before any NQ
+    pub nq: u64,
+    pub hq_after: u64, // on the HQ column, after the NQ
+}
+
+impl HqStrategy {
+    // Check every material the strategy names is in the recipe, so a typo is
+    // caught before crafting rather than silently using NQ
+    pub fn check(&self, item: &Item) -> Result<(), Error> {
+        let names: Vec<&String> = match self {
+            HqStrategy::NqFirst | HqStrategy::HqFirst => return Ok(()),
+            HqStrategy::Counts(counts) => counts.keys().collect(),
+            HqStrategy::Only(names) => names.iter().collect(),
+        };
+        for name in names {
+            let material = item
+                .materials
+                .iter()
+                .find(|m| m.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    failure::format_err!("`{}` doesn't use `{}` as a material", item.name, name)
+                })?;
+            if let HqStrategy::Counts(counts) = self {
+                if counts[name] > material.count {
+                    return Err(failure::format_err!(
+                        "`{}` only uses {}x {}, not {} HQ",
+                        item.name,
+                        material.count,
+                        material.name,
+                        counts[name]
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn presses(&self, material: &Material) -> Presses {
+        let count = material.count;
+        let named = |name: &String| name.eq_ignore_ascii_case(&material.name);
+        match self {
+            HqStrategy::NqFirst => Presses {
+                hq_before: count,
+                nq: count,
+                hq_after: 0,
+            },
+            HqStrategy::HqFirst => Presses {
+                hq_before: 0,
+                nq: count,
+                hq_after: count,
+            },
+            HqStrategy::Counts(counts) => {
+                let hq = counts
+                    .iter()
+                    .find(|(name, _)| named(name))
+                    .map_or(0, |(_, &hq)| hq);
+                Presses {
+                    hq_before: 0,
+                    nq: count - hq,
+                    hq_after: hq,
+                }
+            }
+            HqStrategy::Only(names) => Presses {
+                hq_before: 0,
+                nq: count,
+                hq_after: if names.iter().any(named) { count } else { 0 },
+            },
+        }
+    }
+}
+
+impl FromStr for HqStrategy {
+    type Err = Error;
+
+    // Accepts `nq-first`, `hq-first`, `only:<material>,...` or
+    // `<material>=<count>,...`
+    fn from_str(s: &str) -> Result<HqStrategy, Error> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("nq-first") {
+            return Ok(HqStrategy::NqFirst);
+        }
+        if s.eq_ignore_ascii_case("hq-first") {
+            return Ok(HqStrategy::HqFirst);
+        }
+        if let Some(names) = s.strip_prefix("only:") {
+            let names: Vec<String> = names
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect();
+            if names.is_empty() {
+                return Err(failure::format_err!("`{}` names no materials", s));
+            }
+            return Ok(HqStrategy::Only(names));
+        }
+
+        let mut counts = BTreeMap::new();
+        for part in s.split(',') {
+            let mut split = part.splitn(2, '=');
+            let name = split.next().unwrap_or("").trim();
+            let count = split.next().and_then(|count| count.trim().parse().ok());
+            match count {
+                Some(count) if !name.is_empty() => {
+                    counts.insert(name.to_string(), count);
+                }
+                _ => {
+                    return Err(failure::format_err!(
+                        "unknown HQ strategy `{}`, expected nq-first, hq-first, \
+                         only:<material>,... or <material>=<count>,...",
+                        s
+                    ))
+                }
+            }
+        }
+        Ok(HqStrategy::Counts(counts))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for HqStrategy {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<HqStrategy, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for HqStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HqStrategy::NqFirst => write!(f, "nq-first"),
+            HqStrategy::HqFirst => write!(f, "hq-first"),
+            HqStrategy::Counts(counts) => {
+                let counts: Vec<String> = counts
+                    .iter()
+                    .map(|(name, count)| format!("{}={}", name, count))
+                    .collect();
+                write!(f, "{}", counts.join(","))
+            }
+            HqStrategy::Only(names) => write!(f, "only:{}", names.join(",")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::garland::Recipe;
+
+    fn material(name: &str, count: u64) -> Material {
+        Material {
+            id: 0,
+            name: name.to_string(),
+            count,
+            craftable: false,
+        }
+    }
+
+    #[test]
+    fn parse_strategies() {
+        assert_eq!(
+            "nq-first".parse::<HqStrategy>().unwrap(),
+            HqStrategy::NqFirst
+        );
+        assert_eq!(
+            "HQ-First".parse::<HqStrategy>().unwrap(),
+            HqStrategy::HqFirst
+        );
+        assert_eq!(
+            "only:Loquat, Cumin Seeds".parse::<HqStrategy>().unwrap(),
+            HqStrategy::Only(vec!["Loquat".to_string(), "Cumin Seeds".to_string()])
+        );
+        let counts = "Loquat=2,Crimson Pepper = 1".parse::<HqStrategy>().unwrap();
+        assert_eq!(counts.to_string(), "Crimson Pepper=1,Loquat=2");
+        assert!("hq".parse::<HqStrategy>().is_err());
+        assert!("Loquat=two".parse::<HqStrategy>().is_err());
+        assert!("only:".parse::<HqStrategy>().is_err());
+    }
+
+    #[test]
+    fn strategy_presses() {
+        let loquat = material("Loquat", 3);
+        let pepper = material("Crimson Pepper", 1);
+        let press = |strategy: &str, material: &Material| {
+            let p = strategy.parse::<HqStrategy>().unwrap().presses(material);
+            (p.hq_before, p.nq, p.hq_after)
+        };
+        assert_eq!(press("nq-first", &loquat), (3, 3, 0));
+        assert_eq!(press("hq-first", &loquat), (0, 3, 3));
+        assert_eq!(press("loquat=2", &loquat), (0, 1, 2));
+        assert_eq!(press("loquat=2", &pepper), (0, 1, 0));
+        assert_eq!(press("only:Loquat", &loquat), (0, 3, 3));
+        assert_eq!(press("only:Loquat", &pepper), (0, 1, 0));
+    }
+
+    #[test]
+    fn check_strategy() {
+        let item = Item {
+            id: 19_881,
+            name: "Crimson Cider".to_string(),
+            materials: vec![material("Crimson Pepper", 1), material("Loquat", 3)],
+            recipe: Recipe {
+                id: 3_374,
+                job: None,
+                level: 70,
+                rlvl: 290,
+                stars: 0,
+                progress: 0,
+                quality: 0,
+                durability: 0,
+                yield_count: 1,
+                crystals: vec![],
+            },
+        };
+        let check = |strategy: &str| strategy.parse::<HqStrategy>().unwrap().check(&item);
+        assert!(check("hq-first").is_ok());
+        assert!(check("loquat=3").is_ok());
+        assert_eq!(
+            check("Loquat=4").unwrap_err().to_string(),
+            "`Crimson Cider` only uses 3x Loquat, not 4 HQ"
+        );
+        assert_eq!(
+            check("only:Kudzu Root").unwrap_err().to_string(),
+            "`Crimson Cider` doesn't use `Kudzu Root` as a material"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use failure::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// The Disciples of the Hand, in the order the game lists them
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Job {
+    CRP,
+    BSM,
+    ARM,
+    GSM,
+    LTW,
+    WVR,
+    ALC,
+    CUL,
+}
+
+pub const JOBS: [Job; 8] = [
+    Job::CRP,
+    Job::BSM,
+    Job::ARM,
+    Job::GSM,
+    Job::LTW,
+    Job::WVR,
+    Job::ALC,
+    Job::CUL,
+];
+
+impl Job {
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            Job::CRP => "CRP",
+            Job::BSM => "BSM",
+            Job::ARM => "ARM",
+            Job::GSM => "GSM",
+            Job::LTW => "LTW",
+            Job::WVR => "WVR",
+            Job::ALC => "ALC",
+            Job::CUL => "CUL",
+        }
+    }
+
+    // GarlandTools numbers jobs by their class id, starting with CRP at 8
+    pub fn from_garland_id(id: u64) -> Option<Job> {
+        match id {
+            8..=15 => Some(JOBS[id as usize - 8]),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::CRP => "Carpenter",
+            Job::BSM => "Blacksmith",
+            Job::ARM => "Armorer",
+            Job::GSM => "Goldsmith",
+            Job::LTW => "Leatherworker",
+            Job::WVR => "Weaver",
+            Job::ALC => "Alchemist",
+            Job::CUL => "Culinarian",
+        }
+    }
+}
+
+impl FromStr for Job {
+    type Err = Error;
+
+    // Accepts either the abbreviation or the full job name
+    fn from_str(s: &str) -> Result<Job, Error> {
+        JOBS.iter()
+            .cloned()
+            .find(|job| {
+                job.abbreviation().eq_ignore_ascii_case(s) || job.name().eq_ignore_ascii_case(s)
+            })
+            .ok_or_else(|| failure::format_err!("unknown crafting job `{}`", s))
+    }
+}
+
+impl serde::Serialize for Job {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.abbreviation())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Job {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Job, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.abbreviation())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_job() {
+        assert_eq!("cul".parse::<Job>().unwrap(), Job::CUL);
+        assert_eq!("Goldsmith".parse::<Job>().unwrap(), Job::GSM);
+        assert!("MIN".parse::<Job>().is_err());
+        assert_eq!(Job::from_garland_id(8), Some(Job::CRP));
+        assert_eq!(Job::from_garland_id(15), Some(Job::CUL));
+        assert_eq!(Job::from_garland_id(16), None);
+    }
+}
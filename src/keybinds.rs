@@ -0,0 +1,378 @@
+use failure::Error;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// A physical key on the keyboard. Printable keys are stored lowercase since
+// the game doesn't distinguish between `c` and `C` without a modifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Numpad(u8),
+    Function(u8),
+    Decimal,
+    Enter,
+    Escape,
+    Space,
+    Tab,
+}
+
+// Keys accepted as a single character in the config file.
+const PUNCTUATION: &str = "[]=-,./;'\\`";
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Key, Error> {
+        let lower = s.to_lowercase();
+        let mut chars = lower.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() || PUNCTUATION.contains(c) {
+                return Ok(Key::Char(c));
+            }
+        }
+
+        let numbered = |prefix: &str, max: u8| -> Option<u8> {
+            if !lower.starts_with(prefix) {
+                return None;
+            }
+            lower[prefix.len()..]
+                .parse::<u8>()
+                .ok()
+                .filter(|n| *n <= max)
+        };
+        if let Some(n) = numbered("num", 9) {
+            return Ok(Key::Numpad(n));
+        }
+        if let Some(n) = numbered("f", 12).filter(|n| *n > 0) {
+            return Ok(Key::Function(n));
+        }
+
+        match &*lower {
+            "decimal" => Ok(Key::Decimal),
+            "enter" => Ok(Key::Enter),
+            "escape" | "esc" => Ok(Key::Escape),
+            "space" => Ok(Key::Space),
+            "tab" => Ok(Key::Tab),
+            _ => Err(failure::format_err!("unknown key `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Numpad(n) => write!(f, "num{}", n),
+            Key::Function(n) => write!(f, "f{}", n),
+            Key::Decimal => write!(f, "decimal"),
+            Key::Enter => write!(f, "enter"),
+            Key::Escape => write!(f, "escape"),
+            Key::Space => write!(f, "space"),
+            Key::Tab => write!(f, "tab"),
+        }
+    }
+}
+
+// A key along with the modifiers held while pressing it. In the config file
+// modifiers are written as prefixes: `+` for SHIFT and `^` for CTRL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Keybind {
+    pub key: Key,
+    pub shift: bool,
+    pub ctrl: bool,
+}
+
+impl Keybind {
+    pub fn new(key: Key) -> Keybind {
+        Keybind {
+            key,
+            shift: false,
+            ctrl: false,
+        }
+    }
+}
+
+impl FromStr for Keybind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Keybind, Error> {
+        let mut shift = false;
+        let mut ctrl = false;
+        let mut rest = s.trim();
+        // Always leave at least one character behind for the key itself
+        while rest.len() > 1 {
+            if rest.starts_with('+') {
+                shift = true;
+            } else if rest.starts_with('^') {
+                ctrl = true;
+            } else {
+                break;
+            }
+            rest = &rest[1..];
+        }
+
+        let key = rest
+            .parse::<Key>()
+            .map_err(|e| failure::format_err!("invalid keybind `{}`: {}", s, e))?;
+        Ok(Keybind { key, shift, ctrl })
+    }
+}
+
+impl fmt::Display for Keybind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "^")?;
+        }
+        if self.shift {
+            write!(f, "+")?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+// All of the keys Talan presses. The system binds mirror the entries under
+// Keybind -> System in the game, and crafting binds are keyed by the
+// lowercase action name.
+#[derive(Debug, Clone)]
+pub struct Keybinds {
+    pub cursor_up: Keybind,
+    pub cursor_down: Keybind,
+    pub cursor_left: Keybind,
+    pub cursor_right: Keybind,
+    pub cycle_forward: Keybind,
+    pub cycle_backward: Keybind,
+    pub confirm: Keybind,
+    pub cancel: Keybind,
+    pub chat: Keybind,
+    pub close_window: Keybind,
+    pub crafting_log: Keybind,
+    pub crafting: HashMap<String, Keybind>,
+}
+
+impl Default for Keybinds {
+    fn default() -> Keybinds {
+        Keybinds {
+            cursor_up: Keybind::new(Key::Numpad(8)),
+            cursor_down: Keybind::new(Key::Numpad(2)),
+            cursor_left: Keybind::new(Key::Numpad(4)),
+            cursor_right: Keybind::new(Key::Numpad(6)),
+            cycle_forward: Keybind::new(Key::Numpad(9)),
+            cycle_backward: Keybind::new(Key::Numpad(7)),
+            confirm: Keybind::new(Key::Numpad(0)),
+            cancel: Keybind::new(Key::Decimal),
+            chat: Keybind::new(Key::Enter),
+            close_window: Keybind::new(Key::Escape),
+            crafting_log: Keybind::new(Key::Char('n')),
+            crafting: HashMap::new(),
+        }
+    }
+}
+
+impl Keybinds {
+    // Build the keybinds from the raw `[system_keybinds]` and
+    // `[crafting_keybinds]` tables. Anything not in the system table keeps
+    // its default binding.
+    pub fn from_tables(
+        system: &HashMap<String, String>,
+        crafting: &HashMap<String, String>,
+    ) -> Result<Keybinds, Error> {
+        let mut keybinds = Keybinds::default();
+        for (name, value) in system {
+            let bind = value
+                .parse::<Keybind>()
+                .map_err(|e| failure::format_err!("system keybind `{}`: {}", name, e))?;
+            *keybinds.system_mut(name)? = bind;
+        }
+        for (name, value) in crafting {
+            let bind = value
+                .parse::<Keybind>()
+                .map_err(|e| failure::format_err!("crafting keybind `{}`: {}", name, e))?;
+            keybinds.crafting.insert(name.to_lowercase(), bind);
+        }
+
+        for conflict in keybinds.check_conflicts()? {
+            log::warn!(
+                "{}, neither action will be pressed from the hotbar",
+                conflict
+            );
+        }
+        Ok(keybinds)
+    }
+
+    fn system_mut(&mut self, name: &str) -> Result<&mut Keybind, Error> {
+        let bind = match &*name.to_lowercase() {
+            "cursor up" => &mut self.cursor_up,
+            "cursor down" => &mut self.cursor_down,
+            "cursor left" => &mut self.cursor_left,
+            "cursor right" => &mut self.cursor_right,
+            "cycle forward" => &mut self.cycle_forward,
+            "cycle backward" => &mut self.cycle_backward,
+            "confirm" => &mut self.confirm,
+            "cancel" => &mut self.cancel,
+            "chat" => &mut self.chat,
+            "close window" => &mut self.close_window,
+            "crafting log" => &mut self.crafting_log,
+            _ => return Err(failure::format_err!("unknown system keybind `{}`", name)),
+        };
+        Ok(bind)
+    }
+
+    // Look up the keybind for a crafting action, ignoring case.
+    pub fn action(&self, name: &str) -> Option<Keybind> {
+        self.crafting.get(&name.to_lowercase()).cloned()
+    }
+
+    // System binds sharing a key with anything would send Talan somewhere
+    // unexpected, so those are errors. Actions sharing a key with each other
+    // are returned to be reported, and both are unbound so the wrong one is
+    // never pressed.
+    fn check_conflicts(&mut self) -> Result<Vec<String>, Error> {
+        let system = [
+            ("Cursor Up", self.cursor_up),
+            ("Cursor Down", self.cursor_down),
+            ("Cursor Left", self.cursor_left),
+            ("Cursor Right", self.cursor_right),
+            ("Cycle Forward", self.cycle_forward),
+            ("Cycle Backward", self.cycle_backward),
+            ("Confirm", self.confirm),
+            ("Cancel", self.cancel),
+            ("Chat", self.chat),
+            ("Close Window", self.close_window),
+            ("Crafting Log", self.crafting_log),
+        ];
+        // Sort the crafting binds so errors are reported consistently
+        let mut crafting: Vec<(&str, Keybind)> = self
+            .crafting
+            .iter()
+            .map(|(name, bind)| (name.as_str(), *bind))
+            .collect();
+        crafting.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut seen: HashMap<Keybind, (&str, bool)> = HashMap::new();
+        let mut conflicts = vec![];
+        let mut clashes = vec![];
+        let mut unbind = vec![];
+        let binds = system
+            .iter()
+            .map(|&(name, bind)| (name, bind, true))
+            .chain(crafting.into_iter().map(|(name, bind)| (name, bind, false)));
+        for (name, bind, is_system) in binds {
+            if let Some((other, other_system)) = seen.insert(bind, (name, is_system)) {
+                let conflict = format!("`{}` is bound to both `{}` and `{}`", bind, other, name);
+                if is_system || other_system {
+                    conflicts.push(conflict);
+                } else {
+                    clashes.push(conflict);
+                    unbind.push(other.to_string());
+                    unbind.push(name.to_string());
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(failure::format_err!(
+                "conflicting keybinds: {}",
+                conflicts.join(", ")
+            ));
+        }
+        for name in unbind {
+            self.crafting.remove(&name);
+        }
+        Ok(clashes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keybind_modifiers() {
+        let bind: Keybind = "^+x".parse().unwrap();
+        assert_eq!(bind.key, Key::Char('x'));
+        assert!(bind.shift);
+        assert!(bind.ctrl);
+
+        let bind: Keybind = "+C".parse().unwrap();
+        assert_eq!(bind.key, Key::Char('c'));
+        assert!(bind.shift);
+        assert!(!bind.ctrl);
+
+        let bind: Keybind = "num8".parse().unwrap();
+        assert_eq!(bind, Keybind::new(Key::Numpad(8)));
+    }
+
+    #[test]
+    fn keybind_unknown_key() {
+        assert!("^".parse::<Keybind>().is_err());
+        assert!("+".parse::<Keybind>().is_err());
+        assert!("num10".parse::<Keybind>().is_err());
+        assert!("f0".parse::<Keybind>().is_err());
+        assert!("^pageup".parse::<Keybind>().is_err());
+    }
+
+    #[test]
+    fn keybinds_unknown_system() {
+        let result = Keybinds::from_tables(&table(&[("Jump", "space")]), &HashMap::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn keybinds_conflict() {
+        // Letters ignore case, so these two actions share a key and neither
+        // can be pressed
+        let system = table(&[("Confirm", "=")]);
+        let crafting = table(&[
+            ("Inner Quiet", "c"),
+            ("Hasty Touch", "C"),
+            ("Muscle Memory", "+c"),
+        ]);
+        let mut keybinds = Keybinds::from_tables(&system, &crafting).unwrap();
+        assert_eq!(keybinds.action("Inner Quiet"), None);
+        assert_eq!(keybinds.action("Hasty Touch"), None);
+        assert_eq!(keybinds.action("Muscle Memory").unwrap().to_string(), "+c");
+
+        keybinds
+            .crafting
+            .insert("inner quiet".to_string(), "c".parse().unwrap());
+        keybinds
+            .crafting
+            .insert("hasty touch".to_string(), "c".parse().unwrap());
+        assert_eq!(
+            keybinds.check_conflicts().unwrap(),
+            vec!["`c` is bound to both `hasty touch` and `inner quiet`".to_string()]
+        );
+
+        // Sharing a key with a system bind is an error
+        let crafting = table(&[("Innovation", "num0")]);
+        let err = Keybinds::from_tables(&HashMap::new(), &crafting).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "conflicting keybinds: `num0` is bound to both `Confirm` and `innovation`"
+        );
+    }
+
+    #[test]
+    fn keybinds_tables() {
+        let system = table(&[("Confirm", "="), ("Cycle Backward", "[")]);
+        let crafting = table(&[("Innovation", "r"), ("Muscle Memory", "+c")]);
+        let keybinds = Keybinds::from_tables(&system, &crafting).unwrap();
+        assert_eq!(keybinds.confirm, Keybind::new(Key::Char('=')));
+        assert_eq!(keybinds.cycle_backward, Keybind::new(Key::Char('[')));
+        assert_eq!(keybinds.cursor_up, Keybind::new(Key::Numpad(8)));
+        assert_eq!(
+            keybinds.action("innovation"),
+            Some(Keybind::new(Key::Char('r')))
+        );
+        assert_eq!(keybinds.action("Muscle Memory").unwrap().to_string(), "+c");
+        assert_eq!(keybinds.action("Observe"), None);
+    }
+}
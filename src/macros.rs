@@ -1,3 +1,4 @@
+use crate::actions::{self, ActionInfo};
 use failure::Error;
 use regex::Regex;
 use std::fmt;
@@ -8,6 +9,14 @@ use std::path::PathBuf;
 pub struct Action {
     pub name: String,
     pub wait: u64,
+    pub line: usize, // line in the macro file, starting at 1
+}
+
+impl Action {
+    // The action database entry, if this is a known action
+    pub fn info(&self) -> Option<&'static ActionInfo> {
+        actions::lookup(&self.name)
+    }
 }
 
 impl fmt::Display for Action {
@@ -16,31 +25,119 @@ impl fmt::Display for Action {
     }
 }
 
-fn parse_buffer(buffer: &str) -> Vec<Action> {
+// A single line of a macro. Besides actions, in-game macros can hold
+// explicit waits and echoes (optionally with a sound effect and a wait),
+// which are kept so the macro runs the same way it would in game.
+#[derive(Debug, PartialEq)]
+pub enum Entry {
+    Action(Action),
+    Wait {
+        seconds: u64,
+        line: usize,
+    },
+    Echo {
+        text: String,
+        sound: Option<u64>,
+        wait: u64, // seconds to wait after echoing, 0 without <wait.N>
+        line: usize,
+    },
+}
+
+impl Entry {
+    pub fn action(&self) -> Option<&Action> {
+        match self {
+            Entry::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ACTION_RE: Regex =
+        Regex::new(r#"^/(?i:ac|action)\s+(?:"([^"]+)"|([^"<]*[^"<\s]))\s*(?:(?i:<wait)\.(\d+)>)?$"#)
+            .expect("error compiling regex");
+    static ref WAIT_RE: Regex = Regex::new(r"^(?i)/wait\s+(\d+)$").expect("error compiling regex");
+    static ref SOUND_RE: Regex = Regex::new(r"(?i)\s*<se\.(\d+)>").expect("error compiling regex");
+    static ref ECHO_WAIT_RE: Regex =
+        Regex::new(r"(?i)\s*<wait\.(\d+)>").expect("error compiling regex");
+}
+
+fn parse_buffer(buffer: &str) -> Result<Vec<Entry>, Error> {
     let mut parsed_macros = vec![];
-    buffer
-        .trim()
-        .lines()
-        .for_each(|line| parsed_macros.push(parse_line(line.trim()).unwrap()));
+    for (i, line) in buffer.lines().enumerate() {
+        let entry = parse_entry(line.trim(), i + 1)
+            .map_err(|e| failure::format_err!("line {}: {}", i + 1, e))?;
+        parsed_macros.extend(entry);
+    }
 
-    parsed_macros
+    Ok(parsed_macros)
 }
 
-pub fn parse_file(macros_file: PathBuf) -> Result<Vec<Action>, Error> {
+pub fn parse_file(macros_file: PathBuf) -> Result<Vec<Entry>, Error> {
     let buffer = fs::read_to_string(macros_file)?;
-    Ok(parse_buffer(&buffer))
+    parse_buffer(&buffer)
+}
+
+// Turn a line of a macro into an Entry. Blank lines, comments (`#` or `//`)
+// and commands which only matter to the in-game macro window, such as
+// `/macroicon`, give None.
+fn parse_entry(line: &str, number: usize) -> Result<Option<Entry>, Error> {
+    if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+        return Ok(None);
+    }
+
+    let command = line.split_whitespace().next().unwrap_or("");
+    match &*command.to_lowercase() {
+        "/ac" | "/action" => {
+            // Sound effects do nothing for actions, so they're dropped
+            let line = SOUND_RE.replace_all(line, "");
+            let mut action = parse_line(&line)?;
+            action.line = number;
+            Ok(Some(Entry::Action(action)))
+        }
+        "/wait" => {
+            let values = WAIT_RE
+                .captures(line)
+                .ok_or_else(|| failure::format_err!("Unable to parse wait: `{}`", line))?;
+            Ok(Some(Entry::Wait {
+                seconds: values[1].parse()?,
+                line: number,
+            }))
+        }
+        "/echo" | "/e" => {
+            let sound = match SOUND_RE.captures(line) {
+                Some(values) => Some(values[1].parse()?),
+                None => None,
+            };
+            let wait = match ECHO_WAIT_RE.captures(line) {
+                Some(values) => values[1].parse()?,
+                None => 0,
+            };
+            let text = SOUND_RE.replace_all(&line[command.len()..], "");
+            let text = ECHO_WAIT_RE.replace_all(&text, "");
+            Ok(Some(Entry::Echo {
+                text: text.trim().to_string(),
+                sound,
+                wait,
+                line: number,
+            }))
+        }
+        "/macroicon" | "/micon" | "/macrolock" | "/mlock" => Ok(None),
+        _ => Err(failure::format_err!("Unrecognized macro line: `{}`", line)),
+    }
 }
 
 // Extract the action and wait times for a given line in a macros. Returns a
 // String in the event of an error indicating a malformed macros.
 pub fn parse_line(line: &str) -> Result<Action, Error> {
-    let re = Regex::new(r#"/ac ["]?([a-zA-Z:' ]+[a-zA-Z])["]?(?: <wait.([0-9])>)?"#)
-        .expect("error compiling regex");
-    let values = re
+    let values = ACTION_RE
         .captures(line)
         .ok_or_else(|| failure::format_err!("Unable to parse line: `{}`", line))?;
-    let action = values.get(1).map_or("", |m| m.as_str());
-    let wait = match values.get(2) {
+    let action = values
+        .get(1)
+        .or_else(|| values.get(2))
+        .map_or("", |m| m.as_str());
+    let wait = match values.get(3) {
         Some(x) => x
             .as_str()
             .parse::<u64>()
@@ -48,12 +145,52 @@ pub fn parse_line(line: &str) -> Result<Action, Error> {
         None => 3,
     };
 
+    // Known actions are stored under their canonical name, so the rest of
+    // Talan doesn't need to worry about case or aliases.
+    let name = match actions::lookup(action) {
+        Some(info) => info.name.to_string(),
+        None => action.to_string(),
+    };
+
     Ok(Action {
-        name: action.to_string(),
+        name,
         wait,
+        line: 0,
     })
 }
 
+// Check every action in a macro is one Talan knows about and that the
+// gearset crafting it can use it. All of the problems are reported at once
+// so a macro can be fixed in a single pass.
+pub fn validate(entries: &[Entry], specialist: bool) -> Result<(), Error> {
+    let mut problems = vec![];
+    for action in entries.iter().filter_map(Entry::action) {
+        match action.info() {
+            None => {
+                let problem = match actions::suggest(&action.name) {
+                    Some(suggestion) => format!(
+                        "line {}: unknown action `{}`, did you mean `{}`?",
+                        action.line, action.name, suggestion
+                    ),
+                    None => format!("line {}: unknown action `{}`", action.line, action.name),
+                };
+                problems.push(problem);
+            }
+            Some(info) if info.specialist && !specialist => problems.push(format!(
+                "line {}: `{}` can only be used by a specialist",
+                action.line, action.name
+            )),
+            Some(_) => (),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(failure::format_err!("{}", problems.join("\n")))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -114,10 +251,17 @@ mod tests {
         assert_eq!(entry.wait, 3);
     }
 
+    #[test]
+    fn macros_canonical_name() {
+        let entry = parse_line(r#"/ac "careful synthesis iii" <wait.3>"#).unwrap();
+        assert_eq!(entry.name, "Careful Synthesis III");
+        assert_eq!(entry.info().unwrap().cp, 7);
+    }
+
     #[test]
     fn macros_empty() {
         let result = parse_line(r#""#);
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
     }
 
     #[test]
@@ -130,44 +274,149 @@ mod tests {
         /ac "Byregot's Blessing" <wait.3>
         /ac "Careful Synthesis III" <wait.3>"#;
 
-        let actual = parse_buffer(test_macros);
-        assert_eq!(validate_test_entries(actual), true);
+        let actual = parse_buffer(test_macros).unwrap();
+        assert!(validate_test_entries(actual, 2));
     }
 
     #[test]
     fn macros_file() {
         let actual = parse_file(PathBuf::from("src/test_macro"));
-        assert_eq!(validate_test_entries(actual.unwrap()), true);
+        assert!(validate_test_entries(actual.unwrap(), 1));
+    }
+
+    #[test]
+    fn macros_validate() {
+        let actions = parse_buffer(
+            r#"/ac "Inner Quiet" <wait.2>
+        /ac "Inovation" <wait.2>
+        /ac "Specialty: Reflect" <wait.3>
+        /ac "Teleport" <wait.3>"#,
+        )
+        .unwrap();
+        let err = validate(&actions, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: unknown action `Inovation`, did you mean `Innovation`?\n\
+             line 3: `Specialty: Reflect` can only be used by a specialist\n\
+             line 4: unknown action `Teleport`"
+        );
+
+        let err = validate(&actions, true).unwrap_err();
+        assert!(!err.to_string().contains("specialist"));
+        assert!(validate(&actions[..1], false).is_ok());
+    }
+
+    #[test]
+    fn macros_in_game_text() {
+        let test_macros = r#"/macroicon "Careful Synthesis III"
+        /micon Innovation
+
+        # opener
+        /ac "Inner Quiet" <wait.2>
+        /Action Innovation <Wait.2> <se.3>
+        /WAIT 3
+        /AC Careful Synthesis 3
+        // done
+        /echo Macro #1 finished <se.1>
+        /mlock"#;
+
+        let actual = parse_buffer(test_macros).unwrap();
+        let expected = vec![
+            Entry::Action(Action {
+                name: "Inner Quiet".to_string(),
+                wait: 2,
+                line: 5,
+            }),
+            Entry::Action(Action {
+                name: "Innovation".to_string(),
+                wait: 2,
+                line: 6,
+            }),
+            Entry::Wait {
+                seconds: 3,
+                line: 7,
+            },
+            Entry::Action(Action {
+                name: "Careful Synthesis III".to_string(),
+                wait: 3,
+                line: 8,
+            }),
+            Entry::Echo {
+                text: "Macro #1 finished".to_string(),
+                sound: Some(1),
+                wait: 0,
+                line: 10,
+            },
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn macros_echo_wait() {
+        let actual = parse_buffer("/e Step one <Wait.2>\n/echo done <se.4> <wait.1>").unwrap();
+        let expected = vec![
+            Entry::Echo {
+                text: "Step one".to_string(),
+                sound: None,
+                wait: 2,
+                line: 1,
+            },
+            Entry::Echo {
+                text: "done".to_string(),
+                sound: Some(4),
+                wait: 1,
+                line: 2,
+            },
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn macros_unrecognized_line() {
+        let err = parse_buffer("/ac \"Inner Quiet\"\n/dance\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: Unrecognized macro line: `/dance`");
+
+        let err = parse_buffer("/wait soon").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1: Unable to parse wait: `/wait soon`"
+        );
     }
 
-    fn validate_test_entries(actual: Vec<Action>) -> bool {
-        let expected = [
+    fn validate_test_entries(actual: Vec<Entry>, first_line: usize) -> bool {
+        let expected = vec![
             Action {
                 name: "Comfort Zone".to_string(),
                 wait: 3,
+                line: first_line,
             },
             Action {
                 name: "Inner Quiet".to_string(),
                 wait: 2,
+                line: first_line + 1,
             },
             Action {
                 name: "Great Strides".to_string(),
                 wait: 2,
+                line: first_line + 2,
             },
             Action {
                 name: "Manipulation II".to_string(),
                 wait: 3,
+                line: first_line + 3,
             },
             Action {
                 name: "Byregot's Blessing".to_string(),
                 wait: 3,
+                line: first_line + 4,
             },
             Action {
                 name: "Careful Synthesis III".to_string(),
                 wait: 3,
+                line: first_line + 5,
             },
         ];
 
-        (actual == expected)
+        actual == expected.into_iter().map(Entry::Action).collect::<Vec<_>>()
     }
 }
@@ -1,17 +1,32 @@
+mod actions;
+mod cache;
 mod craft;
+mod crystal;
 mod garland;
+mod hq;
+mod job;
+mod keybinds;
 mod macros;
+mod prerequisites;
+mod provider;
 mod role_actions;
+mod settings;
+mod shopping;
+mod sim;
 mod task;
+mod timing;
 mod ui;
 
-use crate::craft::craft_items;
-use crate::task::Task;
+use crate::cache::{Cache, CacheMode};
+use crate::craft::{craft_items, ActionMode};
+use crate::garland::{GarlandTools, Item};
+use crate::provider::{ItemProvider, LocalItems};
+use crate::role_actions::SavedSlots;
+use crate::settings::{Gearset, PrerequisiteRule, Settings};
+use crate::task::QueueEntry;
 use failure::Error;
-use log;
-use pretty_env_logger;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::ptr::null_mut;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -23,27 +38,126 @@ struct Opt {
     #[structopt(short = "i", default_value = "0")]
     recipe_index: u64,
 
-    /// Path to the file containing the XIV macros to use
-    #[structopt(name = "macro file", parse(from_os_str))]
-    macro_file: PathBuf,
+    /// Path to the file containing the XIV macros to use. Not needed with
+    /// --shopping-list.
+    #[structopt(
+        name = "macro file",
+        parse(from_os_str),
+        raw(required_unless_one = r#"&["queue", "purge_cache", "shopping_list"]"#)
+    )]
+    macro_file: Option<PathBuf>,
 
     /// Name of the item to craft
-    #[structopt(name = "item name")]
-    item_name: String,
+    #[structopt(
+        name = "item name",
+        raw(required_unless_one = r#"&["queue", "purge_cache", "shopping_list"]"#)
+    )]
+    item_name: Option<String>,
+
+    /// Path to a TOML or JSON queue file listing the tasks to craft. When
+    /// set, the macro file and item name arguments are not used.
+    #[structopt(
+        short = "q",
+        long = "queue",
+        parse(from_os_str),
+        raw(conflicts_with_all = r#"&["macro file", "item name"]"#)
+    )]
+    queue: Option<PathBuf>,
 
     /// Number of items to craft
     #[structopt(short = "c", default_value = "1")]
     count: u64,
 
-    /// Gearset to use for this crafting task.
+    /// Gearset to use for this crafting task. 0 uses the gearset set up for
+    /// the recipe's job in [gearsets], if there is one.
     #[structopt(short = "g", default_value = "0")]
     gearset: u64,
 
+    /// Job whose recipe to craft, by abbreviation (e.g. CUL) or name. Needed
+    /// for items with recipes on several jobs unless the gearset's job is
+    /// set in [gearsets] or [gearset_stats].
+    #[structopt(short = "j", long = "job")]
+    job: Option<job::Job>,
+
+    /// How to pick between NQ and HQ materials: nq-first, hq-first,
+    /// only:<material>,... to use HQ for just those materials, or
+    /// <material>=<count>,... for an exact number of HQ.
+    #[structopt(long = "hq", default_value = "nq-first")]
+    hq: hq::HqStrategy,
+
     /// Item(s) will be crafted as collectable
     #[structopt(long = "collectable")]
     collectable: bool,
 
-    /// Do not craft, but attempt to set everything up to do so
+    /// The gearset belongs to a specialist, allowing specialist actions
+    /// such as Specialty: Reflect in the macro. Defaults to the gearset's
+    /// `specialist` setting.
+    #[structopt(long = "specialist")]
+    specialist: bool,
+
+    /// Increase delay between actions and UI navigation. Recommended with
+    /// higher latency or input lag. Uses the high-latency timing profile.
+    #[structopt(short = "d")]
+    delay: bool,
+
+    /// Path to the config file holding keybinds and other settings
+    #[structopt(long = "config", default_value = "config.toml", parse(from_os_str))]
+    config: PathBuf,
+
+    /// Execute crafting actions with the keybinds in [crafting_keybinds]
+    /// instead of typing them. Actions without a keybind are still typed.
+    #[structopt(long = "hotbar")]
+    hotbar: bool,
+
+    /// With --hotbar, refuse to start if a macro uses an action that has no
+    /// keybind rather than typing it.
+    #[structopt(long = "strict", raw(requires = r#""hotbar""#))]
+    strict: bool,
+
+    /// Read item and recipe data from a local JSON file of GarlandTools item
+    /// documents instead of GarlandTools itself.
+    #[structopt(long = "items", parse(from_os_str))]
+    items: Option<PathBuf>,
+
+    /// Only use item data from the GarlandTools cache, however old, and
+    /// never go to the network.
+    #[structopt(long = "offline", raw(conflicts_with = r#""refresh_cache""#))]
+    offline: bool,
+
+    /// Fetch all item data from GarlandTools again, updating the cache.
+    #[structopt(long = "refresh-cache")]
+    refresh_cache: bool,
+
+    /// Empty the GarlandTools cache before doing anything else. With no
+    /// item or queue given, Talan exits after purging.
+    #[structopt(long = "purge-cache")]
+    purge_cache: bool,
+
+    /// Forget the role actions saved from previous runs and clear them on
+    /// each job before slotting the ones needed.
+    #[structopt(long = "reset-role-actions")]
+    reset_role_actions: bool,
+
+    /// Craft any craftable materials of each task first, using the macros
+    /// and gearsets picked by the `[[prerequisites]]` rules in the config.
+    #[structopt(short = "p", long = "prerequisites")]
+    prerequisites: bool,
+
+    /// Print every raw material, crystal and intermediate the tasks need,
+    /// then exit without crafting.
+    #[structopt(long = "shopping-list")]
+    shopping_list: bool,
+
+    /// With --shopping-list, also write the list to a .csv or .json file.
+    #[structopt(
+        long = "export",
+        parse(from_os_str),
+        raw(requires = r#""shopping_list""#)
+    )]
+    export: Option<PathBuf>,
+
+    /// Do not craft, but attempt to set everything up to do so. Prints the
+    /// planned input sequence and estimated duration of each task.
     #[structopt(short = "n")]
     dryrun: bool,
 }
@@ -52,29 +166,145 @@ fn main() -> Result<(), Error> {
     pretty_env_logger::init_timed();
 
     let opt = Opt::from_args();
-    let mut window: ui::WinHandle = null_mut();
-    // Can this becme map err?
-    if !ui::get_window(&mut window) {
-        return Err(failure::format_err!(
-            "Could not find FFXIV window. Is the client running?"
-        ));
+    let profile = if opt.delay {
+        Some("high-latency")
+    } else {
+        None
+    };
+    let settings = if opt.config.exists() {
+        Settings::load(&opt.config, profile)?
+    } else {
+        log::warn!(
+            "config `{}` not found, using default settings",
+            opt.config.display()
+        );
+        Settings {
+            timing: timing::Timing::profile(profile.unwrap_or("normal"))?,
+            ..Settings::default()
+        }
+    };
+    ui::set_keybinds(settings.keybinds);
+    ui::set_timing(settings.timing);
+    role_actions::load_table_file(&settings.role_actions.table_file)?;
+
+    let cache_mode = if opt.offline {
+        CacheMode::Offline
+    } else if opt.refresh_cache {
+        CacheMode::Refresh
+    } else {
+        CacheMode::Normal
+    };
+    let cache = Cache::new(&settings.cache.dir, settings.cache.ttl(), cache_mode);
+    if opt.purge_cache {
+        let removed = cache.purge()?;
+        println!("removed {} cached GarlandTools response(s)", removed);
+        if opt.queue.is_none() && opt.item_name.is_none() {
+            return Ok(());
+        }
+    }
+
+    let items: Box<dyn ItemProvider> = match opt.items {
+        Some(ref path) => Box::new(LocalItems::load(path)?),
+        None => Box::new(GarlandTools::new(cache)),
+    };
+
+    if opt.shopping_list {
+        return shopping_list(&opt, &settings.gearsets, &settings.prerequisites, &*items);
+    }
+
+    // Resolve every task before crafting anything. Errors are all especially
+    // fatal so let them bubble up if they occur.
+    let tasks = match opt.queue {
+        Some(ref queue) => task::load_queue(queue, &settings.gearsets, &*items)?,
+        None => vec![task::resolve_entry(
+            QueueEntry {
+                item: opt.item_name.unwrap(),
+                count: opt.count,
+                macro_file: opt.macro_file.unwrap(),
+                gearset: opt.gearset,
+                index: opt.recipe_index,
+                collectable: opt.collectable,
+                specialist: if opt.specialist { Some(true) } else { None },
+                job: opt.job,
+                hq: opt.hq,
+            },
+            &settings.gearsets,
+            &*items,
+        )?],
+    };
+    let tasks = if opt.prerequisites {
+        prerequisites::expand(tasks, &settings.prerequisites, &settings.gearsets, &*items)?
+    } else {
+        tasks
+    };
+    log::info!("loaded {} task(s)", tasks.len());
+
+    let mode = if opt.hotbar {
+        ActionMode::Hotbar
+    } else {
+        ActionMode::Typed
+    };
+    craft::check_action_mode(&tasks, mode, opt.strict, &ui::keybinds())?;
+
+    let state_file = &settings.role_actions.state_file;
+    let saved = if opt.reset_role_actions {
+        SavedSlots::default()
+    } else {
+        SavedSlots::load(state_file)?
+    };
+
+    // A dry run never talks to the game, so there's no need for its window
+    if opt.dryrun {
+        return craft::dry_run(&tasks, mode, &saved);
     }
 
-    // Grab and parse the config file. Errors are all especially fatal so
-    // let them bubble up if they occur.
-    let macro_contents =
-        macros::parse_file(opt.macro_file).map_err(|e| format!("error parsing macro: `{}`", e));
-
-    let item = garland::fetch_item_info(&opt.item_name)?;
-    log::info!("item information: {}", item);
-    let tasks = vec![Task {
-        item: item,
-        index: opt.recipe_index,
-        count: opt.count,
-        actions: macro_contents.unwrap(),
-        gearset: opt.gearset,
-        collectable: opt.collectable,
-    }];
-    craft_items(window, &tasks);
+    let window = ui::find_window().ok_or_else(|| {
+        failure::format_err!("Could not find FFXIV window. Is the client running?")
+    })?;
+    println!(
+        "crystals needed: {}",
+        crystal::describe(&craft::batch_crystals(&tasks))
+    );
+    craft_items(&window, &tasks, mode, saved, state_file)
+}
+
+// Print the shopping list for a queue, or for a count of one item. Macros
+// don't matter to it, so with an item only that is looked up and the macro
+// can be left out, leaving the item name as the only argument.
+fn shopping_list(
+    opt: &Opt,
+    gearsets: &HashMap<u64, Gearset>,
+    rules: &[PrerequisiteRule],
+    items: &dyn ItemProvider,
+) -> Result<(), Error> {
+    let crafts: Vec<(Item, u64)> = match opt.queue {
+        Some(ref queue) => task::load_queue(queue, gearsets, items)?
+            .into_iter()
+            .map(|task| (task.item, task.count))
+            .collect(),
+        None => {
+            let name = match (&opt.macro_file, &opt.item_name) {
+                (_, Some(name)) => name.clone(),
+                (Some(name), None) => name.to_string_lossy().into_owned(),
+                (None, None) => {
+                    return Err(failure::format_err!(
+                        "--shopping-list needs an item name or a queue"
+                    ))
+                }
+            };
+            let job = opt
+                .job
+                .or_else(|| gearsets.get(&opt.gearset).and_then(|g| g.job));
+            vec![(items.item(&name, job)?, opt.count)]
+        }
+    };
+
+    let crafts: Vec<_> = crafts.iter().map(|(item, count)| (item, *count)).collect();
+    let list = shopping::shopping_list(&crafts, rules, items)?;
+    print!("{}", list);
+    if let Some(ref path) = opt.export {
+        list.export(path)?;
+        println!("shopping list written to `{}`", path.display());
+    }
     Ok(())
 }
@@ -0,0 +1,309 @@
+use crate::crystal;
+use crate::garland::Item;
+use crate::hq::HqStrategy;
+use crate::provider::ItemProvider;
+use crate::settings::{Gearset, PrerequisiteRule};
+use crate::task::{self, Task};
+use failure::Error;
+use std::collections::{HashMap, HashSet};
+
+// An intermediate craft found by walking the craftable materials of the
+// items asked for, along with the rule that matched it
+pub struct Prerequisite<'a> {
+    pub item: Item,
+    pub rule: &'a PrerequisiteRule,
+    pub needed: u64, // number of the item used by the crafts above it
+    pub crafts: u64, // crafts needed to make that many
+}
+
+struct Planner<'a> {
+    rules: &'a [PrerequisiteRule],
+    items: &'a dyn ItemProvider,
+    nodes: Vec<(Item, &'a PrerequisiteRule)>, // every node comes after all of its materials
+    visited: HashSet<String>,
+    visiting: HashSet<String>,
+}
+
+// Find every intermediate needed to craft `count` of each of the given
+// items from raw materials. Intermediates are ordered so each one comes
+// after everything it's made from, and needs shared between items are
+// combined before the yield is taken into account.
+pub fn plan<'a>(
+    roots: &[(&Item, u64)],
+    rules: &'a [PrerequisiteRule],
+    items: &'a dyn ItemProvider,
+) -> Result<Vec<Prerequisite<'a>>, Error> {
+    let mut planner = Planner {
+        rules,
+        items,
+        nodes: vec![],
+        visited: HashSet::new(),
+        visiting: HashSet::new(),
+    };
+    for (root, _) in roots {
+        planner.visiting.insert(root.name.clone());
+        for name in craftable_materials(root) {
+            planner.visit(&name)?;
+        }
+        planner.visiting.remove(&root.name);
+    }
+
+    // Work out how many of each craft are needed, starting from the roots
+    // and passing counts down to materials. Reversing the order guarantees
+    // every craft using a material is counted before the material itself.
+    let mut needed: HashMap<String, u64> = HashMap::new();
+    for &(root, count) in roots {
+        add_materials(&mut needed, root, count);
+    }
+    let mut planned = Vec::with_capacity(planner.nodes.len());
+    for (item, rule) in planner.nodes.into_iter().rev() {
+        let yield_count = u64::from(std::cmp::max(item.recipe.yield_count, 1));
+        let count = needed[&item.name];
+        let crafts = count.div_ceil(yield_count);
+        add_materials(&mut needed, &item, crafts);
+        planned.push(Prerequisite {
+            item,
+            rule,
+            needed: count,
+            crafts,
+        });
+    }
+    planned.reverse();
+    Ok(planned)
+}
+
+// Turn a queue into the list of tasks needed to craft it from raw materials.
+// Every craftable material is crafted first, enough times to cover what the
+// whole queue needs once its yield is taken into account, so intermediates
+// shared between tasks are only crafted once. The queue itself comes last.
+pub fn expand(
+    queue: Vec<Task>,
+    rules: &[PrerequisiteRule],
+    gearsets: &HashMap<u64, Gearset>,
+    items: &dyn ItemProvider,
+) -> Result<Vec<Task>, Error> {
+    let roots: Vec<(&Item, u64)> = queue.iter().map(|t| (&t.item, t.count)).collect();
+    let planned = plan(&roots, rules, items)?;
+    let mut tasks = Vec::with_capacity(planned.len() + queue.len());
+    for Prerequisite {
+        item, rule, crafts, ..
+    } in planned
+    {
+        let gearset = rule
+            .gearset
+            .unwrap_or_else(|| task::job_gearset(gearsets, item.recipe.job));
+        if gearset == 0 {
+            log::warn!(
+                "no gearset for `{}`, it will be crafted with the current job",
+                item.name
+            );
+        }
+        log::info!(
+            "adding {} craft(s) of `{}` as a prerequisite ({} per craft)",
+            crafts,
+            item.name,
+            crystal::describe(&item.recipe.crystals)
+        );
+        let steps = task::load_macro(
+            &rule.macro_file,
+            rule.specialist
+                .unwrap_or_else(|| gearsets.get(&gearset).is_some_and(|g| g.specialist)),
+            item.recipe.job,
+            gearsets,
+        )?;
+        tasks.push(Task {
+            item,
+            count: crafts,
+            index: 0,
+            steps,
+            gearset,
+            collectable: false,
+            crafter: gearsets.get(&gearset).and_then(|g| g.crafter),
+            hq: HqStrategy::default(),
+        });
+    }
+    tasks.extend(queue);
+    Ok(tasks)
+}
+
+fn craftable_materials(item: &Item) -> Vec<String> {
+    item.materials
+        .iter()
+        .filter(|m| m.craftable)
+        .map(|m| m.name.clone())
+        .collect()
+}
+
+fn add_materials(needed: &mut HashMap<String, u64>, item: &Item, crafts: u64) {
+    for material in item.materials.iter().filter(|m| m.craftable) {
+        *needed.entry(material.name.clone()).or_insert(0) += material.count * crafts;
+    }
+}
+
+impl<'a> Planner<'a> {
+    // Add an intermediate and everything under it, depth first
+    fn visit(&mut self, name: &str) -> Result<(), Error> {
+        if self.visited.contains(name) {
+            return Ok(());
+        }
+        if !self.visiting.insert(name.to_string()) {
+            return Err(failure::format_err!(
+                "`{}` is needed to craft itself, giving up",
+                name
+            ));
+        }
+
+        let (item, rule) = self.choose(name)?;
+        for material in craftable_materials(&item) {
+            self.visit(&material)?;
+        }
+
+        self.visiting.remove(name);
+        self.visited.insert(name.to_string());
+        self.nodes.push((item, rule));
+        Ok(())
+    }
+
+    // Find the first rule with a recipe for the item that it matches
+    fn choose(&self, name: &str) -> Result<(Item, &'a PrerequisiteRule), Error> {
+        let mut last_error = None;
+        for rule in self.rules {
+            let item = match self.items.item(name, rule.job) {
+                Ok(item) => item,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            if rule.max_level.is_some_and(|max| item.recipe.level > max) {
+                continue;
+            }
+            return Ok((item, rule));
+        }
+
+        match last_error {
+            Some(e) => Err(failure::format_err!(
+                "no [[prerequisites]] rule can craft `{}`: {}",
+                name,
+                e
+            )),
+            None => Err(failure::format_err!(
+                "no [[prerequisites]] rule matches `{}`",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::job::Job;
+    use crate::provider::LocalItems;
+    use crate::sim::Crafter;
+    use std::path::{Path, PathBuf};
+
+    fn rule(job: Option<Job>, max_level: Option<u32>) -> PrerequisiteRule {
+        PrerequisiteRule {
+            job,
+            max_level,
+            macro_file: PathBuf::from("src/test_macro"),
+            gearset: None,
+            specialist: None,
+        }
+    }
+
+    #[test]
+    fn expand_intermediates() {
+        let items = LocalItems::load(Path::new("src/test_items.json")).unwrap();
+        let crafter = Crafter {
+            level: 290,
+            craftsmanship: 1500,
+            control: 1450,
+            cp: 500,
+        };
+        let mut gearsets = HashMap::new();
+        gearsets.insert(
+            8,
+            Gearset {
+                job: Some(Job::CUL),
+                crafter: Some(crafter),
+                specialist: false,
+            },
+        );
+        let task = Task {
+            item: items.item("Sui-no-Sato Special", None).unwrap(),
+            count: 1,
+            index: 1,
+            steps: vec![],
+            gearset: 8,
+            collectable: true,
+            crafter: None,
+            hq: HqStrategy::default(),
+        };
+
+        // The CUL rule is too low level for the components, so they fall
+        // through to the catch-all rule
+        let rules = [rule(Some(Job::CUL), Some(50)), rule(None, None)];
+        let tasks = expand(vec![task], &rules, &gearsets, &items).unwrap();
+        assert_eq!(tasks.len(), 2);
+
+        // 3 components are needed and each craft makes 2
+        assert_eq!(tasks[0].item.name, "Sui-no-Sato Special Components");
+        assert_eq!(tasks[0].count, 2);
+        assert_eq!(tasks[0].gearset, 8);
+        assert_eq!(tasks[0].crafter, Some(crafter));
+        assert_eq!(tasks[0].actions().count(), 6);
+        assert!(!tasks[0].collectable);
+
+        assert_eq!(tasks[1].item.name, "Sui-no-Sato Special");
+        assert_eq!(tasks[1].count, 1);
+        assert!(tasks[1].collectable);
+    }
+
+    #[test]
+    fn expand_without_rules() {
+        let items = LocalItems::load(Path::new("src/test_items.json")).unwrap();
+        let task = Task {
+            item: items.item("Sui-no-Sato Special", None).unwrap(),
+            count: 4,
+            index: 0,
+            steps: vec![],
+            gearset: 0,
+            collectable: false,
+            crafter: None,
+            hq: HqStrategy::default(),
+        };
+        let rules = [rule(Some(Job::CUL), Some(50))];
+        let err = expand(vec![task], &rules, &HashMap::new(), &items).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no [[prerequisites]] rule matches `Sui-no-Sato Special Components`"
+        );
+    }
+
+    #[test]
+    fn expand_shared_intermediates() {
+        let items = LocalItems::load(Path::new("src/test_items.json")).unwrap();
+        let task = || Task {
+            item: items.item("Sui-no-Sato Special", None).unwrap(),
+            count: 1,
+            index: 1,
+            steps: vec![],
+            gearset: 0,
+            collectable: true,
+            crafter: None,
+            hq: HqStrategy::default(),
+        };
+
+        // Each task needs 3 components, so 6 in all from 3 crafts rather
+        // than 2 crafts for each task
+        let rules = [rule(None, None)];
+        let tasks = expand(vec![task(), task()], &rules, &HashMap::new(), &items).unwrap();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].item.name, "Sui-no-Sato Special Components");
+        assert_eq!(tasks[0].count, 3);
+        assert_eq!(tasks[1].item.name, "Sui-no-Sato Special");
+        assert_eq!(tasks[2].item.name, "Sui-no-Sato Special");
+    }
+}
@@ -0,0 +1,87 @@
+use crate::garland::{self, Item};
+use crate::job::Job;
+use failure::Error;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// A source of item and recipe data
+pub trait ItemProvider {
+    // Look up an item's id by its exact name
+    fn item_id(&self, name: &str) -> Result<u64, Error>;
+
+    // Get an item along with the materials and metadata of its recipe. Items
+    // with recipes on several jobs need `job` to pick one.
+    fn item(&self, name: &str, job: Option<Job>) -> Result<Item, Error>;
+}
+
+// Item data read from a local JSON file holding an array of GarlandTools
+// item documents, in the same layout the API serves them. Useful for
+// crafting without the network, or for testing against fixed data.
+#[derive(Debug)]
+pub struct LocalItems {
+    items: HashMap<String, Value>, // keyed by lowercase item name
+}
+
+impl LocalItems {
+    pub fn load(path: &Path) -> Result<LocalItems, Error> {
+        let buffer = fs::read_to_string(path)
+            .map_err(|e| failure::format_err!("failed to read `{}`: {}", path.display(), e))?;
+        let documents: Vec<Value> = serde_json::from_str(&buffer)
+            .map_err(|e| failure::format_err!("failed to parse `{}`: {}", path.display(), e))?;
+
+        let mut items = HashMap::new();
+        for document in documents {
+            let name = document["item"]["name"].as_str().ok_or_else(|| {
+                failure::format_err!("`{}` has an item without a name", path.display())
+            })?;
+            items.insert(name.to_lowercase(), document);
+        }
+        Ok(LocalItems { items })
+    }
+
+    fn document(&self, name: &str) -> Result<&Value, Error> {
+        self.items
+            .get(&name.to_lowercase())
+            .ok_or_else(|| failure::format_err!("item `{}` not found", name))
+    }
+}
+
+impl ItemProvider for LocalItems {
+    fn item_id(&self, name: &str) -> Result<u64, Error> {
+        self.document(name)?["item"]["id"]
+            .as_u64()
+            .ok_or_else(|| failure::format_err!("item `{}` has no id", name))
+    }
+
+    fn item(&self, name: &str, job: Option<Job>) -> Result<Item, Error> {
+        garland::parse_item(self.document(name)?.clone(), job)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixtures() -> LocalItems {
+        LocalItems::load(Path::new("src/test_items.json")).unwrap()
+    }
+
+    #[test]
+    fn local_items() {
+        let items = fixtures();
+        assert_eq!(items.item_id("crimson cider").unwrap(), 19_881);
+
+        let item = items.item("Sui-no-Sato Special", None).unwrap();
+        assert_eq!(item.materials.len(), 1);
+        assert_eq!(item.materials[0].name, "Sui-no-Sato Special Components");
+        assert_eq!(item.materials[0].count, 3);
+
+        let item = items.item("Sui-no-Sato Special Components", None).unwrap();
+        assert_eq!(item.recipe.job, Some(Job::CUL));
+
+        let err = items.item("Sui-no-Sato", None).unwrap_err();
+        assert_eq!(err.to_string(), "item `Sui-no-Sato` not found");
+    }
+}
@@ -1,52 +1,139 @@
-use crate::craft::{aaction_add, aaction_remove};
-use crate::ui::WinHandle;
+use crate::actions;
+use crate::job::Job;
+use failure::Error;
 use linked_hash_set::LinkedHashSet;
-use log;
-use std::collections::HashSet;
-
-lazy_static::lazy_static! {
-    static ref role_actions: HashSet<&'static str> =  {
-        let mut h = HashSet::new();
-        h.insert("brand of earth");
-        h.insert("brand of fire");
-        h.insert("brand of ice");
-        h.insert("brand of lightning");
-        h.insert("brand of water");
-        h.insert("brand of wind");
-        h.insert("byregot's blessing");
-        h.insert("careful synthesis ii");
-        h.insert("careful synthesis");
-        h.insert("comfort zone");
-        h.insert("flawless synthesis");
-        h.insert("hasty touch");
-        h.insert("ingenuity ii");
-        h.insert("ingenuity");
-        h.insert("innovation");
-        h.insert("maker's mark");
-        h.insert("manipulation");
-        h.insert("muscle memory");
-        h.insert("name of earth");
-        h.insert("name of fire");
-        h.insert("name of ice");
-        h.insert("name of lightning");
-        h.insert("name of water");
-        h.insert("name of wind");
-        h.insert("piece by piece");
-        h.insert("rapid synthesis");
-        h.insert("reclaim");
-        h.insert("rumination");
-        h.insert("steady hand ii");
-        h.insert("tricks of the trade");
-        h.insert("waste not ii");
-        h.insert("waste not");
-        h
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// The game only has room for this many cross-class actions at once
+pub const SLOTS: usize = 10;
+
+// A single `/aaction` command changing which role actions are slotted
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Clear,
+    Add(String),
+    Remove(String),
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Clear => write!(f, "/aaction clear"),
+            Command::Add(action) => write!(f, "/aaction \"{}\" on", action),
+            Command::Remove(action) => write!(f, "/aaction \"{}\" off", action),
+        }
+    }
+}
+
+// A job action other jobs can slot as a role action, with the jobs that
+// learn it and the level each one learns it at. Other jobs can only slot it
+// once one of those has reached that level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleAction {
+    pub name: &'static str,
+    pub jobs: BTreeMap<Job, u32>,
+}
+
+impl RoleAction {
+    // Whether the job learns the action itself, rather than slotting it
+    pub fn is_native(&self, job: Job) -> bool {
+        self.jobs.contains_key(&job)
+    }
+}
+
+// Role actions by their canonical names. The table changes with game
+// patches, so it's kept as data rather than code, and can be replaced at
+// startup without a rebuild. The copy built into Talan is used otherwise.
+static TABLE: OnceLock<HashMap<&'static str, RoleAction>> = OnceLock::new();
+
+fn table() -> &'static HashMap<&'static str, RoleAction> {
+    TABLE.get_or_init(|| {
+        load_table(include_str!("role_actions.json")).expect("invalid role_actions.json")
+    })
+}
+
+// Use the role action table in a file instead of the built in one, if the
+// file exists. This has to happen before any role action is looked up.
+pub fn load_table_file(path: &Path) -> Result<(), Error> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            log::info!(
+                "no role action table at `{}`, using the built in one",
+                path.display()
+            );
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(failure::format_err!(
+                "error reading `{}`: {}",
+                path.display(),
+                e
+            ))
+        }
     };
+    let table = load_table(&json).map_err(|e| {
+        failure::format_err!("invalid role action table `{}`: {}", path.display(), e)
+    })?;
+    log::info!("loaded role actions from `{}`", path.display());
+    TABLE
+        .set(table)
+        .map_err(|_| failure::format_err!("the role action table is already in use"))
+}
+
+// An entry in a job's list in role_actions.json
+#[derive(serde_derive::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableEntry {
+    name: String,
+    level: u32,
+}
+
+// Read a table of role actions, listing each job's actions with the level
+// they're learned at. An action can be listed under more than one job.
+fn load_table(json: &str) -> Result<HashMap<&'static str, RoleAction>, Error> {
+    let jobs: BTreeMap<Job, Vec<TableEntry>> = serde_json::from_str(json)?;
+    let mut table: HashMap<&'static str, RoleAction> = HashMap::new();
+    for (job, entries) in jobs {
+        for entry in entries {
+            let info = actions::lookup(&entry.name)
+                .ok_or_else(|| failure::format_err!("unknown role action `{}`", entry.name))?;
+            let action = table.entry(info.name).or_insert_with(|| RoleAction {
+                name: info.name,
+                jobs: BTreeMap::new(),
+            });
+            if action.jobs.insert(job, entry.level).is_some() {
+                return Err(failure::format_err!(
+                    "role action `{}` is listed twice for {}",
+                    info.name,
+                    job
+                ));
+            }
+        }
+    }
+    Ok(table)
+}
+
+// Find the role action with a name or alias, ignoring case
+pub fn lookup(action: &str) -> Option<&'static RoleAction> {
+    actions::lookup(action).and_then(|info| table().get(info.name))
 }
 
-#[derive(Debug)]
+// The role actions one task needs, and the job it's crafted on if that's
+// known. Each job has its own slots in the game.
+#[derive(Debug, Clone, Default)]
+pub struct Stage {
+    pub job: Option<Job>,
+    pub actions: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct RoleActions {
-    // TODO: Figure out how to push this iterator out to the RoleAction struct
-    window: WinHandle,
     pub current_actions: LinkedHashSet<String>,
 }
 
@@ -54,15 +141,14 @@ pub struct RoleActions {
 // for LRU-like behavior, ensuring that as we add AdditionalActions they will be older
 // actions not referenced in the current macro.
 impl RoleActions {
-    pub fn new(window: WinHandle) -> RoleActions {
-        RoleActions {
-            window,
-            current_actions: LinkedHashSet::new(),
-        }
+    pub fn new() -> RoleActions {
+        RoleActions::default()
     }
 
-    pub fn is_role_action(&self, action: &str) -> bool {
-        role_actions.contains(&*action.to_lowercase())
+    // Whether an action has to be slotted to use it on a job. A job's own
+    // actions never do. With no job known every job action is assumed to.
+    pub fn is_role_action(&self, action: &str, job: Option<Job>) -> bool {
+        lookup(action).is_some_and(|role_action| !job.is_some_and(|job| role_action.is_native(job)))
     }
 
     #[allow(dead_code)]
@@ -72,42 +158,218 @@ impl RoleActions {
 
     #[allow(dead_code)]
     pub fn contains(&self, action: &str) -> bool {
-        self.current_actions.contains(action)
+        match actions::lookup(action) {
+            Some(info) => self.current_actions.contains(info.name),
+            None => false,
+        }
     }
 
-    // Returns Some() if the craft engine needs to remove the returned action so that it
-    // can add the new onee
-    pub fn add_action(&mut self, action: &str) {
-        if !self.is_role_action(action) {
-            panic!("provided action is not a role action: `{}`", action);
+    fn from_slots(slots: &[String]) -> RoleActions {
+        RoleActions {
+            current_actions: slots.iter().cloned().collect(),
         }
+    }
+
+    // Slot an action, evicting the least recently used one if there's no
+    // room. Returns the commands the game needs to match.
+    pub fn add_action(&mut self, action: &str) -> Vec<Command> {
+        let action = canonical(action);
 
         // If insert returns false then the action was already in the set and no action
         // needs to be taken. It has the side effect of moving it to the back.
         if !self.current_actions.insert(action.to_string()) {
-            return;
+            return vec![];
         }
 
         // If we now have more than 10 actions we need to remove the front element so there
         // is space for the element we're adding next.
-        if self.current_actions.len() > 10 {
+        let mut commands = vec![];
+        if self.current_actions.len() > SLOTS {
             let old_action = self.current_actions.pop_front().unwrap();
-            log::debug!("removing role action \"{}\"", old_action);
-            aaction_remove(self.window, &old_action);
+            commands.push(Command::Remove(old_action));
+        }
+        commands.push(Command::Add(action.to_string()));
+        commands
+    }
+}
+
+fn canonical(action: &str) -> &'static str {
+    match lookup(action) {
+        Some(role_action) => role_action.name,
+        None => panic!("provided action is not a role action: `{}`", action),
+    }
+}
+
+// The distinct role actions in a list, by their canonical names
+fn distinct(actions: &[String]) -> Vec<&'static str> {
+    let mut distinct: Vec<&'static str> = vec![];
+    for action in actions {
+        let action = canonical(action);
+        if !distinct.contains(&action) {
+            distinct.push(action);
         }
-        log::debug!("adding role action \"{}\"", action);
-        aaction_add(self.window, action);
     }
+    distinct
+}
+
+// The role actions of a macro that don't fit in the slots once the ones
+// before them are slotted. A macro with any of these can't be crafted.
+pub fn overflow(actions: &[String]) -> Vec<&'static str> {
+    distinct(actions).into_iter().skip(SLOTS).collect()
+}
+
+// The role actions slotted on each job when Talan last ran, so the next run
+// only has to change what's different
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Default, PartialEq)]
+pub struct SavedSlots {
+    jobs: BTreeMap<Job, Vec<String>>,
+}
+
+impl SavedSlots {
+    // Load the saved slots, starting afresh if there are none yet. Anything
+    // unreadable is dropped, which just means clearing that job's slots.
+    pub fn load(path: &Path) -> Result<SavedSlots, Error> {
+        let buffer = match fs::read_to_string(path) {
+            Ok(buffer) => buffer,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(SavedSlots::default()),
+            Err(e) => {
+                return Err(failure::format_err!(
+                    "failed to read `{}`: {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+        let mut saved: SavedSlots = match serde_json::from_str(&buffer) {
+            Ok(saved) => saved,
+            Err(e) => {
+                log::warn!(
+                    "ignoring corrupt role action state `{}`: {}",
+                    path.display(),
+                    e
+                );
+                return Ok(SavedSlots::default());
+            }
+        };
+        saved.jobs.retain(|job, slots| {
+            let valid = slots.len() <= SLOTS
+                && slots
+                    .iter()
+                    .all(|action| lookup(action).is_some_and(|info| !info.is_native(*job)));
+            if !valid {
+                log::warn!("ignoring invalid saved role actions for {}", job);
+            }
+            valid
+        });
+        for slots in saved.jobs.values_mut() {
+            *slots = distinct(slots).into_iter().map(String::from).collect();
+        }
+        Ok(saved)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|e| failure::format_err!("failed to write `{}`: {}", path.display(), e))
+    }
+
+    pub fn get(&self, job: Job) -> Option<&Vec<String>> {
+        self.jobs.get(&job)
+    }
+
+    pub fn set(&mut self, job: Job, slots: Vec<String>) {
+        self.jobs.insert(job, slots);
+    }
+}
+
+// The `/aaction` commands to run before each task of a queue
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub commands: Vec<Vec<Command>>, // one list per task
+    pub slots: Vec<Vec<String>>,     // what the task's job has slotted once they've run
+    pub lru_count: usize,            // commands the old LRU approach would have needed
+}
+
+impl Plan {
+    pub fn count(&self) -> usize {
+        self.commands.iter().map(Vec::len).sum()
+    }
+
+    pub fn saved(&self) -> usize {
+        self.lru_count.saturating_sub(self.count())
+    }
+}
+
+// Plan the role action changes for a whole queue up front. Jobs start with
+// their saved slots, or are cleared the first time they're used when
+// nothing is known about them. Stages on an unknown job are cleared every
+// time, since they could be on any job. When a slot has to be freed, the
+// action whose next use is furthest away is evicted, oldest first on ties.
+// Actions the task itself needs are never evicted. The LRU count is kept
+// alongside for comparison.
+pub fn plan(stages: &[Stage], saved: &SavedSlots) -> Plan {
+    let needs: Vec<Vec<&'static str>> = stages.iter().map(|s| distinct(&s.actions)).collect();
+
+    // The next stage on the same job using an action
+    let next_use = |job: Option<Job>, action: &str, after: usize| -> usize {
+        (after + 1..stages.len())
+            .find(|&j| job.is_some() && stages[j].job == job && needs[j].contains(&action))
+            .unwrap_or(usize::MAX)
+    };
+
+    let mut plan = Plan::default();
+    let mut jobs: HashMap<Option<Job>, RoleActions> = HashMap::new();
+    let mut lru_jobs: HashMap<Option<Job>, RoleActions> = HashMap::new();
+    for (i, stage) in stages.iter().enumerate() {
+        let mut commands = vec![];
+        if stage.job.is_none() {
+            jobs.remove(&None);
+            lru_jobs.remove(&None);
+        }
+        let slotted = jobs.entry(stage.job).or_insert_with(|| {
+            let known = stage.job.and_then(|job| saved.get(job));
+            if known.is_none() {
+                commands.push(Command::Clear);
+                plan.lru_count += 1;
+            }
+            RoleActions::from_slots(known.map_or(&[], |slots| slots))
+        });
+        let lru = lru_jobs.entry(stage.job).or_insert_with(|| slotted.clone());
+        for action in &stage.actions {
+            plan.lru_count += lru.add_action(action).len();
+        }
+
+        for &action in &needs[i] {
+            if slotted.current_actions.contains(action) {
+                continue;
+            }
+            if slotted.current_actions.len() >= SLOTS {
+                let victim = slotted
+                    .current_actions
+                    .iter()
+                    .filter(|slot| !needs[i].contains(&slot.as_str()))
+                    .min_by_key(|slot| std::cmp::Reverse(next_use(stage.job, slot, i)))
+                    .cloned()
+                    .expect("check_role_actions refuses macros with more role actions than slots");
+                slotted.current_actions.remove(&victim);
+                commands.push(Command::Remove(victim));
+            }
+            slotted.current_actions.insert(action.to_string());
+            commands.push(Command::Add(action.to_string()));
+        }
+        plan.commands.push(commands);
+        plan.slots
+            .push(slotted.current_actions.iter().cloned().collect());
+    }
+    plan
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::ptr::null_mut;
+
     #[test]
     fn test_role_actions() {
-        let window: WinHandle = null_mut();
-        let mut ra = RoleActions::new(window);
+        let mut ra = RoleActions::new();
         ra.add_action("Tricks of the Trade");
         ra.add_action("Byregot's Blessing");
         ra.add_action("Tricks of the Trade");
@@ -121,10 +383,236 @@ mod test {
         ra.add_action("name of earth");
         ra.add_action("name of fire");
         ra.add_action("name of ice");
-        ra.add_action("name of lightning");
+        let commands = ra.add_action("name of lightning");
         assert_eq!(10, ra.count());
-        assert_eq!(false, ra.contains("Tricks of the Trade"));
-        assert_eq!(false, ra.contains("Byregot's Blessing"));
+        assert!(!ra.contains("Tricks of the Trade"));
+        assert!(!ra.contains("Byregot's Blessing"));
+        assert_eq!(
+            commands,
+            vec![
+                Command::Remove("Tricks of the Trade".to_string()),
+                Command::Add("Name of Lightning".to_string()),
+            ]
+        );
         println!("{:?}", ra);
     }
+
+    fn stage(job: Job, actions: &[&str]) -> Stage {
+        Stage {
+            job: Some(job),
+            actions: actions.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn role_action_table_file() {
+        // A missing file leaves the built in table in place
+        assert!(load_table_file(Path::new("src/missing_role_actions.json")).is_ok());
+        let err = load_table_file(Path::new("src/test_queue.toml")).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("invalid role action table `src/test_queue.toml`"));
+    }
+
+    #[test]
+    fn role_actions_by_job() {
+        let ra = RoleActions::new();
+        // Careful Synthesis is learned by both Weavers and Culinarians
+        assert!(!ra.is_role_action("Careful Synthesis", Some(Job::CUL)));
+        assert!(!ra.is_role_action("careful synthesis", Some(Job::WVR)));
+        assert!(ra.is_role_action("Careful Synthesis", Some(Job::ALC)));
+        assert!(ra.is_role_action("Careful Synthesis", None));
+        assert!(!ra.is_role_action("Basic Synthesis", Some(Job::CUL)));
+        assert!(!ra.is_role_action("Teleport", None));
+
+        let action = lookup("Careful Synthesis 2").unwrap();
+        assert_eq!(action.name, "Careful Synthesis II");
+        assert_eq!(
+            action.jobs.iter().collect::<Vec<_>>(),
+            vec![(&Job::WVR, &50)]
+        );
+        let action = lookup("Careful Synthesis").unwrap();
+        assert_eq!(
+            action.jobs.iter().collect::<Vec<_>>(),
+            vec![(&Job::WVR, &15), (&Job::CUL, &15)]
+        );
+        assert_eq!(table().len(), 32);
+
+        assert_eq!(
+            load_table(r#"{"CUL": [{"name": "Hasty Touch", "level": 15}, {"name": "Teleport", "level": 20}]}"#)
+                .unwrap_err()
+                .to_string(),
+            "unknown role action `Teleport`"
+        );
+        assert_eq!(
+            load_table(
+                r#"{"CUL": [{"name": "Hasty Touch", "level": 15}, {"name": "hasty touch", "level": 20}]}"#
+            )
+            .unwrap_err()
+            .to_string(),
+            "role action `Hasty Touch` is listed twice for CUL"
+        );
+    }
+
+    #[test]
+    fn plan_alternating_macros() {
+        let shared = [
+            "Comfort Zone",
+            "Byregot's Blessing",
+            "Innovation",
+            "Ingenuity II",
+            "Manipulation",
+            "Steady Hand II",
+            "Waste Not II",
+        ];
+        let mut a = shared.to_vec();
+        a.extend(&["Maker's Mark", "Piece by Piece"]);
+        let mut b = shared.to_vec();
+        b.extend(&["Reclaim", "Rumination"]);
+        let stages = vec![
+            stage(Job::CUL, &a),
+            stage(Job::CUL, &b),
+            stage(Job::CUL, &a),
+            stage(Job::CUL, &a),
+            stage(Job::CUL, &b),
+        ];
+        let plan = plan(&stages, &SavedSlots::default());
+
+        // Nothing is known about the slots to start with, so they're cleared
+        // before the first macro fills 9 of them. The second only needs 2
+        // more, so one of the first macro's own actions has to go.
+        assert_eq!(plan.commands[0].len(), 10);
+        assert_eq!(plan.commands[0][0], Command::Clear);
+        assert_eq!(
+            plan.commands[1],
+            vec![
+                Command::Add("Reclaim".to_string()),
+                Command::Remove("Maker's Mark".to_string()),
+                Command::Add("Rumination".to_string()),
+            ]
+        );
+        // Neither of the second macro's own actions is needed until the
+        // last task, so the oldest of them makes room
+        assert_eq!(
+            plan.commands[2],
+            vec![
+                Command::Remove("Reclaim".to_string()),
+                Command::Add("Maker's Mark".to_string()),
+            ]
+        );
+        assert!(plan.commands[3].is_empty());
+        // Neither of the first macro's own actions is used again, so the
+        // oldest goes
+        assert_eq!(
+            plan.commands[4],
+            vec![
+                Command::Remove("Piece by Piece".to_string()),
+                Command::Add("Reclaim".to_string()),
+            ]
+        );
+        assert_eq!(plan.count(), 17);
+        assert!(plan.lru_count > plan.count());
+        assert_eq!(plan.saved(), plan.lru_count - 17);
+    }
+
+    #[test]
+    fn overflowing_macro() {
+        let actions: Vec<String> = vec![
+            "Comfort Zone",
+            "Byregot's Blessing",
+            "Innovation",
+            "Ingenuity II",
+            "Manipulation",
+            "Steady Hand II",
+            "Waste Not II",
+            "Maker's Mark",
+            "Piece by Piece",
+            "innovation",
+            "Reclaim",
+            "Rumination",
+            "Name of Fire",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        assert!(overflow(&actions[..11]).is_empty());
+        assert_eq!(overflow(&actions), vec!["Rumination", "Name of Fire"]);
+    }
+
+    #[test]
+    fn plan_with_saved_slots() {
+        let mut saved = SavedSlots::default();
+        saved.set(Job::ALC, vec!["Innovation".to_string()]);
+        let stages = vec![
+            stage(Job::CUL, &["Comfort Zone", "Innovation", "comfort zone"]),
+            stage(Job::ALC, &["Innovation"]),
+            stage(Job::CUL, &["Innovation"]),
+            stage(Job::ALC, &["Innovation", "Reclaim"]),
+        ];
+        let plan = plan(&stages, &saved);
+        assert_eq!(
+            plan.commands[0],
+            vec![
+                Command::Clear,
+                Command::Add("Comfort Zone".to_string()),
+                Command::Add("Innovation".to_string()),
+            ]
+        );
+        // ALC already has what it needs, and switching back to CUL doesn't
+        // lose its slots
+        assert!(plan.commands[1].is_empty());
+        assert!(plan.commands[2].is_empty());
+        assert_eq!(plan.commands[3], vec![Command::Add("Reclaim".to_string())]);
+        assert_eq!(plan.slots[2], vec!["Comfort Zone", "Innovation"]);
+        assert_eq!(plan.slots[3], vec!["Innovation", "Reclaim"]);
+        assert_eq!(plan.lru_count, 4);
+    }
+
+    #[test]
+    fn plan_unknown_job() {
+        let mut saved = SavedSlots::default();
+        saved.set(Job::CUL, vec!["Innovation".to_string()]);
+        let unknown = Stage {
+            job: None,
+            actions: vec!["Innovation".to_string()],
+        };
+        let stages = vec![
+            unknown.clone(),
+            stage(Job::CUL, &["Innovation", "Comfort Zone"]),
+            unknown,
+        ];
+        let plan = plan(&stages, &saved);
+        // Whatever job is active is cleared every time, and the saved CUL
+        // slots are neither used nor changed for it
+        let cleared = vec![Command::Clear, Command::Add("Innovation".to_string())];
+        assert_eq!(plan.commands[0], cleared);
+        assert_eq!(
+            plan.commands[1],
+            vec![Command::Add("Comfort Zone".to_string())]
+        );
+        assert_eq!(plan.commands[2], cleared);
+        assert_eq!(plan.slots[1], vec!["Innovation", "Comfort Zone"]);
+    }
+
+    #[test]
+    fn saved_slots_file() {
+        let path = std::env::temp_dir().join(format!("talan-slots-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(SavedSlots::load(&path).unwrap(), SavedSlots::default());
+
+        let mut saved = SavedSlots::default();
+        saved.set(Job::CUL, vec!["Innovation".to_string()]);
+        saved.save(&path).unwrap();
+        assert_eq!(SavedSlots::load(&path).unwrap(), saved);
+
+        // Slots that don't make sense are forgotten rather than trusted
+        fs::write(
+            &path,
+            r#"{"jobs": {"CUL": ["innovation", "Innovation"], "ALC": ["Basic Touch"]}}"#,
+        )
+        .unwrap();
+        let loaded = SavedSlots::load(&path).unwrap();
+        assert_eq!(loaded, saved);
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,345 @@
+use crate::job::Job;
+use crate::keybinds::Keybinds;
+use crate::sim::{self, Crafter};
+use crate::timing::Timing;
+use config::ConfigError;
+use failure::Error;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Everything Talan reads out of config.toml
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub keybinds: Keybinds,
+    pub timing: Timing,
+    pub gearsets: HashMap<u64, Gearset>, // keyed by gearset number, from both gearset sections
+    pub cache: CacheSettings,
+    pub prerequisites: Vec<PrerequisiteRule>,
+    pub role_actions: RoleActionSettings,
+}
+
+// A `[[prerequisites]]` rule, deciding how intermediate crafts are made.
+// Rules are tried in order and the first one matching the recipe is used.
+#[derive(serde_derive::Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PrerequisiteRule {
+    #[serde(default)]
+    pub job: Option<Job>, // only match recipes for this job
+    #[serde(default)]
+    pub max_level: Option<u32>, // only match recipes up to this job level
+    pub macro_file: PathBuf,
+    #[serde(default)]
+    pub gearset: Option<u64>, // defaults to the gearset set up for the recipe's job
+    #[serde(default)]
+    pub specialist: Option<bool>, // defaults to whether the gearset is a specialist's
+}
+
+// Where GarlandTools responses are cached and for how long they're trusted
+#[derive(serde_derive::Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct CacheSettings {
+    pub dir: PathBuf,
+    pub ttl_hours: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> CacheSettings {
+        CacheSettings {
+            dir: PathBuf::from("cache"),
+            ttl_hours: 24 * 7,
+        }
+    }
+}
+
+impl CacheSettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_hours * 3600)
+    }
+}
+
+// Where the role actions slotted on each job are remembered between runs,
+// and where to find a newer table of which actions are role actions
+#[derive(serde_derive::Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct RoleActionSettings {
+    pub state_file: PathBuf,
+    pub table_file: PathBuf,
+}
+
+impl Default for RoleActionSettings {
+    fn default() -> RoleActionSettings {
+        RoleActionSettings {
+            state_file: PathBuf::from("role_action_state.json"),
+            table_file: PathBuf::from("role_actions.json"),
+        }
+    }
+}
+
+// What Talan knows about one of the player's gearsets
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Gearset {
+    pub job: Option<Job>,
+    pub crafter: Option<Crafter>,
+    pub specialist: bool, // allows specialist actions in macros crafted with it
+}
+
+// A `[gearset_stats.N]` table. The stats are as shown on the character
+// window, and must either all be given or all be left out.
+#[derive(serde_derive::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct GearsetStats {
+    job: Option<Job>,
+    #[serde(default)]
+    specialist: bool,
+    level: Option<u32>,
+    craftsmanship: Option<u32>,
+    control: Option<u32>,
+    cp: Option<u32>,
+}
+
+// A `[gearsets.JOB]` table, naming the gearset a job is crafted with. Stats
+// are optional, the same as in `[gearset_stats.N]`.
+#[derive(serde_derive::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JobGearset {
+    gearset: u64,
+    #[serde(default)]
+    specialist: bool,
+    level: Option<u32>,
+    craftsmanship: Option<u32>,
+    control: Option<u32>,
+    cp: Option<u32>,
+}
+
+impl Settings {
+    // `profile` replaces the timing profile named in the config, though
+    // individual timing fields set in the config still apply on top of it.
+    pub fn load(path: &Path, profile: Option<&str>) -> Result<Settings, Error> {
+        let mut config = config::Config::default();
+        config.merge(config::File::from(path))?;
+
+        let system: HashMap<String, String> = get_or_default(&config, "system_keybinds")?;
+        let crafting: HashMap<String, String> = get_or_default(&config, "crafting_keybinds")?;
+        let keybinds = Keybinds::from_tables(&system, &crafting)
+            .map_err(|e| failure::format_err!("{}: {}", path.display(), e))?;
+
+        let mut timing: HashMap<String, String> = get_or_default(&config, "timing")?;
+        let config_profile = timing.remove("profile");
+        let timing = match profile.or(config_profile.as_deref()) {
+            Some(name) => Timing::profile(name)?,
+            None => Timing::default(),
+        }
+        .with_overrides(&timing)
+        .map_err(|e| failure::format_err!("{}: {}", path.display(), e))?;
+
+        let stats: HashMap<String, GearsetStats> = get_or_default(&config, "gearset_stats")?;
+        let mut gearsets = HashMap::new();
+        for (gearset, stats) in stats {
+            let gearset = gearset.parse::<u64>().map_err(|_| {
+                failure::format_err!(
+                    "{}: [gearset_stats] keys must be gearset numbers, found `{}`",
+                    path.display(),
+                    gearset
+                )
+            })?;
+            let crafter = crafter(
+                (stats.level, stats.craftsmanship, stats.control, stats.cp),
+                &format!("[gearset_stats.{}]", gearset),
+            )
+            .map_err(|e| failure::format_err!("{}: {}", path.display(), e))?;
+            let gearset_info = Gearset {
+                job: stats.job,
+                crafter,
+                specialist: stats.specialist,
+            };
+            gearsets.insert(gearset, gearset_info);
+        }
+
+        let jobs: HashMap<String, JobGearset> = get_or_default(&config, "gearsets")?;
+        for (job, stats) in jobs {
+            let job = job.parse::<Job>().map_err(|_| {
+                failure::format_err!(
+                    "{}: [gearsets] keys must be job abbreviations, found `{}`",
+                    path.display(),
+                    job
+                )
+            })?;
+            let crafter = crafter(
+                (stats.level, stats.craftsmanship, stats.control, stats.cp),
+                &format!("[gearsets.{}]", job),
+            )
+            .map_err(|e| failure::format_err!("{}: {}", path.display(), e))?;
+            if gearsets.contains_key(&stats.gearset) {
+                return Err(failure::format_err!(
+                    "{}: gearset {} is set up more than once",
+                    path.display(),
+                    stats.gearset
+                ));
+            }
+            let gearset_info = Gearset {
+                job: Some(job),
+                crafter,
+                specialist: stats.specialist,
+            };
+            gearsets.insert(stats.gearset, gearset_info);
+        }
+
+        let cache = get_or_default(&config, "cache")?;
+        let mut role_actions: RoleActionSettings = get_or_default(&config, "role_actions")?;
+
+        // Macro, state and table paths are relative to the config file, like
+        // queue files
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        if role_actions.state_file.is_relative() {
+            role_actions.state_file = base.join(&role_actions.state_file);
+        }
+        if role_actions.table_file.is_relative() {
+            role_actions.table_file = base.join(&role_actions.table_file);
+        }
+        let mut prerequisites: Vec<PrerequisiteRule> = get_or_default(&config, "prerequisites")?;
+        for rule in &mut prerequisites {
+            if rule.macro_file.is_relative() {
+                rule.macro_file = base.join(&rule.macro_file);
+            }
+        }
+
+        Ok(Settings {
+            keybinds,
+            timing,
+            gearsets,
+            cache,
+            prerequisites,
+            role_actions,
+        })
+    }
+}
+
+// Crafter stats are either all given or all left out
+fn crafter(
+    stats: (Option<u32>, Option<u32>, Option<u32>, Option<u32>),
+    table: &str,
+) -> Result<Option<Crafter>, Error> {
+    match stats {
+        (Some(level), Some(craftsmanship), Some(control), Some(cp)) => Ok(Some(Crafter {
+            level: sim::crafter_level(level),
+            craftsmanship,
+            control,
+            cp,
+        })),
+        (None, None, None, None) => Ok(None),
+        _ => Err(failure::format_err!(
+            "{} needs all of level, craftsmanship, control and cp",
+            table
+        )),
+    }
+}
+
+// Sections are all optional, so a missing key falls back to the default
+// rather than being an error.
+fn get_or_default<T: DeserializeOwned + Default>(
+    config: &config::Config,
+    key: &str,
+) -> Result<T, Error> {
+    match config.get::<T>(key) {
+        Ok(value) => Ok(value),
+        Err(ConfigError::NotFound(_)) => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keybinds::{Key, Keybind};
+
+    #[test]
+    fn load_default_config() {
+        let settings = Settings::load(Path::new("config.toml"), None).unwrap();
+        assert_eq!(settings.timing, Timing::NORMAL);
+        let keybinds = settings.keybinds;
+        assert_eq!(keybinds.confirm, Keybind::new(Key::Char('=')));
+        assert_eq!(keybinds.crafting_log, Keybind::new(Key::Char('n')));
+        assert_eq!(keybinds.cursor_up, Keybind::new(Key::Numpad(8)));
+        assert_eq!(
+            keybinds.action("Careful Synthesis III"),
+            Some(Keybind::new(Key::Char('2')))
+        );
+        // Both bound to `c`, so neither is pressed
+        assert_eq!(keybinds.action("Hasty Touch"), None);
+        assert_eq!(keybinds.action("Inner Quiet"), None);
+        assert_eq!(
+            settings.gearsets[&8],
+            Gearset {
+                job: Some(Job::CUL),
+                crafter: Some(Crafter {
+                    level: 290,
+                    craftsmanship: 1500,
+                    control: 1450,
+                    cp: 500,
+                }),
+                specialist: false,
+            }
+        );
+    }
+
+    #[test]
+    fn job_gearsets() {
+        let settings = Settings::load(Path::new("config.toml"), None).unwrap();
+        assert_eq!(
+            crate::task::job_gearset(&settings.gearsets, Some(Job::CUL)),
+            8
+        );
+        assert_eq!(
+            crate::task::job_gearset(&settings.gearsets, Some(Job::ALC)),
+            0
+        );
+        assert_eq!(crate::task::job_gearset(&settings.gearsets, None), 0);
+
+        assert_eq!(
+            crafter((Some(70), Some(1500), None, None), "[gearsets.CUL]")
+                .unwrap_err()
+                .to_string(),
+            "[gearsets.CUL] needs all of level, craftsmanship, control and cp"
+        );
+        assert_eq!(
+            crafter((None, None, None, None), "[gearsets.CUL]").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn load_cache_settings() {
+        let settings = Settings::load(Path::new("config.toml"), None).unwrap();
+        assert_eq!(settings.cache, CacheSettings::default());
+        assert_eq!(settings.cache.ttl(), Duration::from_secs(7 * 24 * 3600));
+    }
+
+    #[test]
+    fn load_role_action_settings() {
+        let settings = Settings::load(Path::new("config.toml"), None).unwrap();
+        assert_eq!(settings.role_actions, RoleActionSettings::default());
+    }
+
+    #[test]
+    fn load_prerequisite_rules() {
+        let settings = Settings::load(Path::new("config.toml"), None).unwrap();
+        assert_eq!(
+            settings.prerequisites[0],
+            PrerequisiteRule {
+                job: Some(Job::CUL),
+                max_level: Some(69),
+                macro_file: PathBuf::from("macros/turn-ins.txt"),
+                gearset: None,
+                specialist: None,
+            }
+        );
+    }
+
+    #[test]
+    fn load_timing_profile() {
+        let settings = Settings::load(Path::new("config.toml"), Some("high-latency")).unwrap();
+        assert_eq!(settings.timing, Timing::HIGH_LATENCY);
+    }
+}
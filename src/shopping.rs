@@ -0,0 +1,240 @@
+use crate::crystal;
+use crate::garland::{Item, Material};
+use crate::prerequisites::{self, Prerequisite};
+use crate::provider::ItemProvider;
+use crate::settings::PrerequisiteRule;
+use failure::Error;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// One line of a shopping list
+#[derive(serde_derive::Serialize, Debug, PartialEq)]
+pub struct Line {
+    pub id: u64,
+    pub name: String,
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crafts: Option<u64>, // only for intermediates
+}
+
+// Everything that has to be bought or gathered for a batch of crafts, with
+// the intermediates Talan will craft along the way
+#[derive(serde_derive::Serialize, Debug, Default)]
+pub struct ShoppingList {
+    pub materials: Vec<Line>,
+    pub crystals: Vec<Line>,
+    pub intermediates: Vec<Line>,
+}
+
+// Totals up counts of each material across crafts
+#[derive(Default)]
+struct Tally {
+    lines: Vec<Line>,
+    index: HashMap<u64, usize>,
+}
+
+impl Tally {
+    fn add(&mut self, material: &Material, crafts: u64) {
+        let count = material.count * crafts;
+        match self.index.get(&material.id) {
+            Some(&i) => self.lines[i].count += count,
+            None => {
+                self.index.insert(material.id, self.lines.len());
+                self.lines.push(Line {
+                    id: material.id,
+                    name: material.name.clone(),
+                    count,
+                    crafts: None,
+                });
+            }
+        }
+    }
+}
+
+// Build the shopping list for crafting each item the given number of times.
+// Craftable materials are expanded all the way down to raw materials, using
+// the job from the first matching `[[prerequisites]]` rule if there is one.
+pub fn shopping_list(
+    crafts: &[(&Item, u64)],
+    rules: &[PrerequisiteRule],
+    items: &dyn ItemProvider,
+) -> Result<ShoppingList, Error> {
+    // Macros don't matter here, so anything no rule covers can still be
+    // listed as long as it only has one recipe
+    let mut rules = rules.to_vec();
+    rules.push(PrerequisiteRule {
+        job: None,
+        max_level: None,
+        macro_file: PathBuf::new(),
+        gearset: None,
+        specialist: None,
+    });
+    let planned = prerequisites::plan(crafts, &rules, items)?;
+
+    let mut materials = Tally::default();
+    let mut crystals = vec![];
+    let mut add = |item: &Item, count: u64| {
+        for material in item.materials.iter().filter(|m| !m.craftable) {
+            materials.add(material, count);
+        }
+        crystal::add(&mut crystals, &item.recipe.crystals, count);
+    };
+    for &(item, count) in crafts {
+        add(item, count);
+    }
+    for Prerequisite { item, crafts, .. } in &planned {
+        add(item, *crafts);
+    }
+
+    let mut list = ShoppingList {
+        materials: materials.lines,
+        crystals: crystals
+            .into_iter()
+            .map(|(crystal, count)| Line {
+                id: crystal.id(),
+                name: crystal.to_string(),
+                count,
+                crafts: None,
+            })
+            .collect(),
+        intermediates: planned
+            .iter()
+            .map(|p| Line {
+                id: p.item.id,
+                name: p.item.name.clone(),
+                count: p.needed,
+                crafts: Some(p.crafts),
+            })
+            .collect(),
+    };
+    list.materials.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(list)
+}
+
+// Quote a CSV field if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl ShoppingList {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,id,name,count,crafts\n");
+        let sections = vec![
+            ("material", &self.materials),
+            ("crystal", &self.crystals),
+            ("intermediate", &self.intermediates),
+        ];
+        for (kind, lines) in sections {
+            for line in lines {
+                csv.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    kind,
+                    line.id,
+                    csv_field(&line.name),
+                    line.count,
+                    line.crafts.map_or(String::new(), |c| c.to_string())
+                ));
+            }
+        }
+        csv
+    }
+
+    // Write the list to a `.csv` or `.json` file, picked by extension
+    pub fn export(&self, path: &Path) -> Result<(), Error> {
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => self.to_csv(),
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => {
+                return Err(failure::format_err!(
+                    "can't tell how to export `{}`, use a .csv or .json file",
+                    path.display()
+                ))
+            }
+        };
+        fs::write(path, contents)
+            .map_err(|e| failure::format_err!("failed to write `{}`: {}", path.display(), e))
+    }
+}
+
+impl fmt::Display for ShoppingList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "materials:")?;
+        for line in &self.materials {
+            writeln!(f, "  {}x {}", line.count, line.name)?;
+        }
+        writeln!(f, "crystals:")?;
+        for line in &self.crystals {
+            writeln!(f, "  {}x {}", line.count, line.name)?;
+        }
+        writeln!(f, "intermediates:")?;
+        for line in &self.intermediates {
+            writeln!(
+                f,
+                "  {}x {} ({} craft(s))",
+                line.count,
+                line.name,
+                line.crafts.unwrap_or(0)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::provider::LocalItems;
+
+    #[test]
+    fn shopping_list_for_batch() {
+        let items = LocalItems::load(Path::new("src/test_items.json")).unwrap();
+        let special = items.item("Sui-no-Sato Special", None).unwrap();
+        let cider = items.item("Crimson Cider", None).unwrap();
+        let list = shopping_list(&[(&special, 1), (&cider, 2)], &[], &items).unwrap();
+
+        let counts = |lines: &[Line]| -> Vec<(String, u64)> {
+            lines.iter().map(|l| (l.name.clone(), l.count)).collect()
+        };
+        assert_eq!(
+            counts(&list.materials),
+            vec![
+                ("Crimson Pepper".to_string(), 2),
+                ("Cumin Seeds".to_string(), 2),
+                ("Jhammel Ginger".to_string(), 4),
+                ("Kudzu Root".to_string(), 2),
+                ("Loquat".to_string(), 10),
+            ]
+        );
+        assert_eq!(
+            counts(&list.crystals),
+            vec![
+                ("Water Shard".to_string(), 7),
+                ("Fire Crystal".to_string(), 8),
+                ("Water Crystal".to_string(), 8),
+            ]
+        );
+        assert_eq!(
+            list.intermediates,
+            vec![Line {
+                id: 22_401,
+                name: "Sui-no-Sato Special Components".to_string(),
+                count: 3,
+                crafts: Some(2),
+            }]
+        );
+
+        let csv = list.to_csv();
+        assert!(csv.starts_with("kind,id,name,count,crafts\nmaterial,19872,Crimson Pepper,2,\n"));
+        assert!(csv.ends_with("intermediate,22401,Sui-no-Sato Special Components,3,2\n"));
+        assert_eq!(
+            csv_field("Grade 3 \"Tincture\", HQ"),
+            "\"Grade 3 \"\"Tincture\"\", HQ\""
+        );
+    }
+}
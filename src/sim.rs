@@ -0,0 +1,492 @@
+use crate::actions::{ActionInfo, WHOLE_CRAFT};
+use crate::macros::Action;
+use std::collections::HashMap;
+use std::fmt;
+
+// The parts of a recipe that matter to a synthesis. Levels are recipe
+// levels (rlvl) rather than job levels, for both the recipe and the crafter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recipe {
+    pub level: u32,
+    pub progress: u32,
+    pub quality: u32,
+    pub durability: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crafter {
+    pub level: u32,
+    pub craftsmanship: u32,
+    pub control: u32,
+    pub cp: u32,
+}
+
+// Convert a job level into the recipe level scale the simulator works in.
+// Levels past 50 are compressed, with a level 70 crafter matching rlvl 290.
+pub fn crafter_level(job_level: u32) -> u32 {
+    const HEAVENSWARD: [u32; 10] = [120, 125, 130, 133, 136, 139, 142, 145, 148, 150];
+    const STORMBLOOD: [u32; 10] = [260, 265, 270, 273, 276, 279, 282, 285, 288, 290];
+    match job_level {
+        0..=50 => job_level,
+        51..=60 => HEAVENSWARD[job_level as usize - 51],
+        _ => STORMBLOOD[std::cmp::min(job_level, 70) as usize - 61],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Finish {
+    Complete,   // progress reached the recipe's requirement
+    Broken,     // durability ran out first
+    Incomplete, // the macro ended before either happened
+}
+
+// An action the simulator could not use. The game refuses these too, so the
+// macro carries on with the next action.
+#[derive(Debug, PartialEq)]
+pub struct Skipped {
+    pub line: usize,
+    pub name: String,
+    pub reason: &'static str,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub progress: u32,
+    pub quality: u32,
+    pub durability: i32,
+    pub cp: u32,
+    pub finish: Finish,
+    pub step: usize, // steps taken when the craft finished, broke or the macro ran out
+    pub line: usize, // macro line of the last action used
+    pub skipped: Vec<Skipped>,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let finish = match self.finish {
+            Finish::Complete => "completed",
+            Finish::Broken => "broke",
+            Finish::Incomplete => "did not finish by",
+        };
+        write!(
+            f,
+            "progress {}, quality {}, durability {}, CP {}; {} at step {} (line {})",
+            self.progress, self.quality, self.durability, self.cp, finish, self.step, self.line
+        )?;
+        for skipped in &self.skipped {
+            write!(
+                f,
+                "\n  line {}: `{}` skipped, {}",
+                skipped.line, skipped.name, skipped.reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Synthesis state as a macro is stepped through
+struct State<'a> {
+    recipe: &'a Recipe,
+    crafter: &'a Crafter,
+    progress: u32,
+    quality: u32,
+    durability: i32,
+    cp: u32,
+    step: usize,
+    inner_quiet: u32, // stacks, 0 when Inner Quiet isn't active
+    buffs: HashMap<&'static str, u32>,
+    used_name: bool, // Name of the Elements can only be used once a craft
+}
+
+// Step through a macro assuming every action succeeds and the condition
+// stays Normal, so the same macro always gives the same outcome. Actions
+// which rely on a Good condition or on chance are skipped rather than
+// guessed at.
+pub fn simulate<'a, I>(recipe: &Recipe, crafter: &Crafter, actions: I) -> Outcome
+where
+    I: IntoIterator<Item = &'a Action>,
+{
+    let mut state = State {
+        recipe,
+        crafter,
+        progress: 0,
+        quality: 0,
+        durability: recipe.durability as i32,
+        cp: crafter.cp,
+        step: 0,
+        inner_quiet: 0,
+        buffs: HashMap::new(),
+        used_name: false,
+    };
+    let mut skipped = vec![];
+    let mut finish = Finish::Incomplete;
+    let mut line = 0;
+
+    for action in actions {
+        let info = match action.info() {
+            Some(info) => info,
+            None => {
+                skipped.push(Skipped::new(action, "unknown action"));
+                continue;
+            }
+        };
+        // Collectable Synthesis and the like don't touch the synthesis
+        if !info.gcd {
+            continue;
+        }
+        if let Err(reason) = state.apply(info) {
+            skipped.push(Skipped::new(action, reason));
+            continue;
+        }
+        line = action.line;
+
+        if state.progress >= recipe.progress {
+            finish = Finish::Complete;
+            break;
+        }
+        if state.durability <= 0 {
+            finish = Finish::Broken;
+            break;
+        }
+        state.tick(info);
+    }
+
+    Outcome {
+        progress: state.progress,
+        quality: state.quality,
+        durability: state.durability,
+        cp: state.cp,
+        finish,
+        step: state.step,
+        line,
+        skipped,
+    }
+}
+
+impl Skipped {
+    fn new(action: &Action, reason: &'static str) -> Skipped {
+        Skipped {
+            line: action.line,
+            name: action.name.clone(),
+            reason,
+        }
+    }
+}
+
+impl<'a> State<'a> {
+    fn has(&self, buff: &str) -> bool {
+        self.buffs.contains_key(buff)
+    }
+
+    // Use an action, or give the reason it can't be used
+    fn apply(&mut self, info: &'static ActionInfo) -> Result<(), &'static str> {
+        self.check(info)?;
+        self.cp -= info.cp;
+        self.step += 1;
+
+        let mut durability = info.durability;
+        if self.has("Waste Not") || self.has("Waste Not II") {
+            durability = durability.div_ceil(2);
+        }
+        self.durability -= durability as i32;
+
+        let remaining = self.recipe.progress.saturating_sub(self.progress);
+        let progress = match info.name {
+            "Piece by Piece" | "Muscle Memory" => remaining.div_ceil(3),
+            "Flawless Synthesis" => 40,
+            _ => self.progress_gain(info),
+        };
+        self.progress += progress;
+
+        let quality = self.quality_gain(info);
+        if quality > 0 {
+            self.quality += quality;
+            self.buffs.remove("Great Strides");
+            if self.inner_quiet > 0 {
+                self.inner_quiet = std::cmp::min(self.inner_quiet + 1, 11);
+            }
+        }
+
+        match info.name {
+            "Inner Quiet" => self.inner_quiet = 1,
+            "Byregot's Blessing" => self.inner_quiet = 0,
+            "Byregot's Miracle" => self.inner_quiet = self.inner_quiet.div_ceil(2),
+            "Rumination" => {
+                let stacks = self.inner_quiet - 1;
+                let cp = std::cmp::min((21 * stacks - stacks * stacks + 10) / 2, 60);
+                self.restore_cp(cp);
+                self.inner_quiet = 0;
+            }
+            "Master's Mend" => self.restore_durability(30),
+            "Master's Mend II" => self.restore_durability(60),
+            "Specialty: Reinforce" => self.restore_durability(25),
+            "Specialty: Refurbish" => self.restore_cp(65),
+            "Specialty: Reflect" => self.inner_quiet = std::cmp::min(self.inner_quiet + 3, 11),
+            _ => (),
+        }
+        if info.name.starts_with("Specialty: ") {
+            self.buffs.remove("Initial Preparations");
+        }
+        if info.name.starts_with("Name of ") {
+            self.used_name = true;
+        }
+        Ok(())
+    }
+
+    fn check(&self, info: &ActionInfo) -> Result<(), &'static str> {
+        if info.cp > self.cp {
+            return Err("not enough CP");
+        }
+        match info.name {
+            "Tricks of the Trade" | "Precise Touch" | "Byregot's Brow" => {
+                Err("it needs a Good or Excellent condition")
+            }
+            "Trained Hand" => Err("Whistle While You Work is not simulated"),
+            "Muscle Memory" if self.step > 0 => Err("it can only be used on the first step"),
+            "Inner Quiet" if self.inner_quiet > 0 => Err("Inner Quiet is already active"),
+            "Byregot's Blessing" | "Byregot's Miracle" | "Specialty: Reflect"
+                if self.inner_quiet == 0 =>
+            {
+                Err("it needs Inner Quiet")
+            }
+            "Rumination" if self.inner_quiet < 2 => Err("it needs Inner Quiet stacks"),
+            "Prudent Touch" if self.has("Waste Not") || self.has("Waste Not II") => {
+                Err("it can't be used under Waste Not")
+            }
+            name if name.starts_with("Specialty: ") && !self.has("Initial Preparations") => {
+                Err("it needs Initial Preparations")
+            }
+            name if name.starts_with("Name of ") && self.used_name => {
+                Err("Name of the Elements was already used")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Positive level differences add 5% a level to progress, up to 25%.
+    // Negative ones take 5% a level from both progress and quality, up to
+    // 50%. Ingenuity removes the penalty.
+    fn level_difference(&self) -> i32 {
+        let difference = self.crafter.level as i32 - self.recipe.level as i32;
+        if self.has("Ingenuity") || self.has("Ingenuity II") {
+            std::cmp::max(difference, 0)
+        } else {
+            difference
+        }
+    }
+
+    fn progress_gain(&self, info: &ActionInfo) -> u32 {
+        let mut efficiency = info.progress;
+        if efficiency == 0 {
+            return 0;
+        }
+        if let Some(element) = info.name.strip_prefix("Brand of ") {
+            if self.has(&format!("Name of {}", element)) {
+                let remaining = 1.0 - f64::from(self.progress) / f64::from(self.recipe.progress);
+                efficiency += 2 * (remaining * 100.0).ceil() as u32;
+            }
+        }
+
+        let difference = self.level_difference().clamp(-10, 5);
+        let base = f64::from(self.crafter.craftsmanship) * 0.21 + 2.0;
+        let modifier = 1.0 + 0.05 * f64::from(difference);
+        (base * modifier * f64::from(efficiency) / 100.0) as u32
+    }
+
+    fn quality_gain(&self, info: &ActionInfo) -> u32 {
+        let mut efficiency = match info.name {
+            "Byregot's Blessing" => 100 + 20 * (self.inner_quiet - 1),
+            "Byregot's Miracle" => 100 + 15 * (self.inner_quiet - 1),
+            _ => info.quality,
+        };
+        if efficiency == 0 {
+            return 0;
+        }
+        if self.has("Great Strides") {
+            efficiency += 100;
+        }
+
+        let base_control = f64::from(self.crafter.control);
+        let mut control = base_control;
+        if self.inner_quiet > 1 {
+            control += base_control * 0.2 * f64::from(self.inner_quiet - 1);
+        }
+        if self.has("Innovation") || self.has("Innovative Touch") {
+            control += base_control * 0.5;
+        }
+
+        let difference = self.level_difference().clamp(-10, 0);
+        let base = control * 0.36 + 34.0;
+        let modifier = 1.0 + 0.05 * f64::from(difference);
+        (base * modifier * f64::from(efficiency) / 100.0) as u32
+    }
+
+    fn restore_cp(&mut self, cp: u32) {
+        self.cp = std::cmp::min(self.cp + cp, self.crafter.cp);
+    }
+
+    fn restore_durability(&mut self, durability: i32) {
+        self.durability =
+            std::cmp::min(self.durability + durability, self.recipe.durability as i32);
+    }
+
+    // Count down buffs at the end of a step. Buffs only start counting on
+    // the step after they're applied.
+    fn tick(&mut self, info: &'static ActionInfo) {
+        if self.has("Manipulation") {
+            self.restore_durability(10);
+        }
+        if self.has("Manipulation II") {
+            self.restore_durability(5);
+        }
+        if self.has("Comfort Zone") {
+            self.restore_cp(8);
+        }
+        for steps in self.buffs.values_mut() {
+            if *steps != WHOLE_CRAFT {
+                *steps -= 1;
+            }
+        }
+        self.buffs.retain(|_, steps| *steps > 0);
+
+        if info.buff > 0 {
+            self.buffs.insert(info.name, info.buff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::macros;
+    use std::path::PathBuf;
+
+    const CRAFTER: Crafter = Crafter {
+        level: 300,
+        craftsmanship: 1500,
+        control: 1450,
+        cp: 500,
+    };
+
+    fn action(name: &str, line: usize) -> Action {
+        Action {
+            name: name.to_string(),
+            wait: 3,
+            line,
+        }
+    }
+
+    #[test]
+    fn crafter_levels() {
+        assert_eq!(crafter_level(50), 50);
+        assert_eq!(crafter_level(60), 150);
+        assert_eq!(crafter_level(70), 290);
+    }
+
+    #[test]
+    fn simulate_test_macro() {
+        let recipe = Recipe {
+            level: 290,
+            progress: 400,
+            quality: 5000,
+            durability: 40,
+        };
+        let steps = macros::parse_file(PathBuf::from("src/test_macro")).unwrap();
+        let outcome = simulate(
+            &recipe,
+            &CRAFTER,
+            steps.iter().filter_map(macros::Entry::action),
+        );
+        assert_eq!(outcome.finish, Finish::Complete);
+        assert_eq!(outcome.step, 6);
+        assert_eq!(outcome.line, 6);
+        assert!(outcome.progress >= 400);
+        assert!(outcome.quality > 0);
+        // Comfort Zone, Inner Quiet, Great Strides, Manipulation II and
+        // Byregot's Blessing, with Comfort Zone returning 8 CP a step
+        assert_eq!(outcome.cp, 500 - 66 - 18 - 32 - 96 - 24 - 7 + 4 * 8);
+        assert!(outcome.skipped.is_empty());
+    }
+
+    #[test]
+    fn simulate_breaks() {
+        let recipe = Recipe {
+            level: 300,
+            progress: 10_000,
+            quality: 5000,
+            durability: 20,
+        };
+        let actions = vec![
+            action("Basic Synthesis", 1),
+            action("Basic Synthesis", 2),
+            action("Basic Synthesis", 3),
+        ];
+        let outcome = simulate(&recipe, &CRAFTER, &actions);
+        assert_eq!(outcome.finish, Finish::Broken);
+        assert_eq!(outcome.step, 2);
+        assert_eq!(outcome.line, 2);
+        assert_eq!(outcome.durability, 0);
+        // 1500 * 0.21 + 2 at the same level as the recipe
+        assert_eq!(outcome.progress, 2 * 317);
+    }
+
+    #[test]
+    fn simulate_skips_unusable_actions() {
+        let recipe = Recipe {
+            level: 300,
+            progress: 10_000,
+            quality: 5000,
+            durability: 40,
+        };
+        let crafter = Crafter { cp: 20, ..CRAFTER };
+        let actions = vec![
+            action("Tricks of the Trade", 1),
+            action("Inner Quiet", 2),
+            action("Master's Mend", 3),
+            action("Basic Synthesis", 4),
+        ];
+        let outcome = simulate(&recipe, &crafter, &actions);
+        assert_eq!(outcome.finish, Finish::Incomplete);
+        assert_eq!(outcome.step, 2);
+        assert_eq!(outcome.cp, 2);
+        assert_eq!(outcome.durability, 30);
+        assert_eq!(
+            outcome.skipped,
+            vec![
+                Skipped {
+                    line: 1,
+                    name: "Tricks of the Trade".to_string(),
+                    reason: "it needs a Good or Excellent condition",
+                },
+                Skipped {
+                    line: 3,
+                    name: "Master's Mend".to_string(),
+                    reason: "not enough CP",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn simulate_waste_not_and_manipulation() {
+        let recipe = Recipe {
+            level: 300,
+            progress: 10_000,
+            quality: 5000,
+            durability: 40,
+        };
+        let actions = vec![
+            action("Basic Synthesis", 1),
+            action("Basic Synthesis", 2),
+            action("Manipulation", 3),
+            action("Waste Not", 4),
+            action("Basic Synthesis", 5),
+        ];
+        let outcome = simulate(&recipe, &CRAFTER, &actions);
+        // Manipulation restores 10 after the Waste Not and final steps, and
+        // the last synthesis only costs 5 durability
+        assert_eq!(outcome.durability, 35);
+        assert_eq!(outcome.cp, 500 - 88 - 56);
+        assert_eq!(outcome.progress, 3 * 317);
+    }
+}
@@ -1,14 +1,364 @@
 use crate::garland::Item;
-use crate::macros::Action;
+use crate::hq::HqStrategy;
+use crate::job::Job;
+use crate::macros::{self, Action, Entry};
+use crate::provider::ItemProvider;
+use crate::role_actions;
+use crate::settings::Gearset;
+use crate::sim::{self, Crafter};
+use failure::Error;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 // A task represents crafting a specific item a given number of times
 // using a provided macro.
 #[derive(Debug)]
 pub struct Task {
-    pub item: Item,           // Item structure for name, id, and materials
-    pub count: u64,           // number of items to craft
-    pub index: u64,           // index of the recipe if a search returns multiple (default: 0)
-    pub actions: Vec<Action>, // List of actions for the task (ie: xiv macro)
-    pub gearset: u64,         // Gearset to switch to for crafting
-    pub collectable: bool,    // craft collectables
+    pub item: Item,               // Item structure for name, id, and materials
+    pub count: u64,               // number of items to craft
+    pub index: u64,               // index of the recipe if a search returns multiple (default: 0)
+    pub steps: Vec<Entry>,        // List of macro entries for the task (ie: xiv macro)
+    pub gearset: u64,             // Gearset to switch to for crafting
+    pub collectable: bool,        // craft collectables
+    pub crafter: Option<Crafter>, // stats of the gearset, if configured
+    pub hq: HqStrategy,           // how to pick between NQ and HQ materials
+}
+
+impl Task {
+    // The crafting actions of the macro, skipping waits and echoes
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        self.steps.iter().filter_map(Entry::action)
+    }
+}
+
+// A single entry in a queue file. These mirror the command line options
+// and are turned into full Tasks once the item and macro are resolved.
+#[derive(serde_derive::Deserialize, Debug, PartialEq)]
+pub struct QueueEntry {
+    pub item: String,
+    #[serde(default = "default_count")]
+    pub count: u64,
+    pub macro_file: PathBuf,
+    #[serde(default)]
+    pub gearset: u64,
+    #[serde(default)]
+    pub index: u64,
+    #[serde(default)]
+    pub collectable: bool,
+    #[serde(default)]
+    pub specialist: Option<bool>, // defaults to whether the gearset is a specialist's
+    #[serde(default)]
+    pub job: Option<Job>,
+    #[serde(default)]
+    pub hq: HqStrategy,
+}
+
+#[derive(serde_derive::Deserialize, Debug)]
+struct Queue {
+    tasks: Vec<QueueEntry>,
+}
+
+fn default_count() -> u64 {
+    1
+}
+
+// Read the entries out of a queue file. The format is picked from the file
+// extension, so both TOML and JSON queues are supported. Relative macro
+// paths are resolved against the directory holding the queue file.
+pub fn parse_queue(path: &Path) -> Result<Vec<QueueEntry>, Error> {
+    let mut settings = config::Config::default();
+    settings.merge(config::File::from(path))?;
+    let queue: Queue = settings.try_into()?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let entries = queue
+        .tasks
+        .into_iter()
+        .map(|mut entry| {
+            if entry.macro_file.is_relative() {
+                entry.macro_file = base.join(&entry.macro_file);
+            }
+            entry
+        })
+        .collect();
+    Ok(entries)
+}
+
+// Load a queue file and resolve every entry into a Task. Item lookups and
+// macro parsing all happen here so a bad entry is caught before any
+// crafting starts rather than halfway through the batch.
+pub fn load_queue(
+    path: &Path,
+    gearsets: &HashMap<u64, Gearset>,
+    items: &dyn ItemProvider,
+) -> Result<Vec<Task>, Error> {
+    let entries = parse_queue(path)?;
+    if entries.is_empty() {
+        return Err(failure::format_err!(
+            "queue `{}` contains no tasks",
+            path.display()
+        ));
+    }
+
+    let mut tasks = Vec::with_capacity(entries.len());
+    for entry in entries {
+        tasks.push(resolve_entry(entry, gearsets, items)?);
+    }
+    Ok(tasks)
+}
+
+// Parse a macro file and check every action in it can be used
+pub fn load_macro(
+    path: &Path,
+    specialist: bool,
+    job: Option<Job>,
+    gearsets: &HashMap<u64, Gearset>,
+) -> Result<Vec<Entry>, Error> {
+    let steps = macros::parse_file(path.to_path_buf())
+        .map_err(|e| failure::format_err!("error parsing macro `{}`: {}", path.display(), e))?;
+    macros::validate(&steps, specialist)
+        .and_then(|_| check_role_action_levels(&steps, job, gearsets))
+        .map_err(|e| failure::format_err!("invalid macro `{}`:\n{}", path.display(), e))?;
+    Ok(steps)
+}
+
+// Refuse role actions none of the jobs they come from has learned yet. A
+// job's level is taken from the stats of its gearsets, and without any it's
+// assumed to be high enough.
+fn check_role_action_levels(
+    steps: &[Entry],
+    job: Option<Job>,
+    gearsets: &HashMap<u64, Gearset>,
+) -> Result<(), Error> {
+    let mut problems = vec![];
+    for action in steps.iter().filter_map(Entry::action) {
+        let role_action = match role_actions::lookup(&action.name) {
+            Some(role_action) if !job.is_some_and(|job| role_action.is_native(job)) => role_action,
+            _ => continue,
+        };
+        let learned = role_action.jobs.iter().any(|(&source, &level)| {
+            gearsets
+                .values()
+                .filter(|gearset| gearset.job == Some(source))
+                .filter_map(|gearset| gearset.crafter.as_ref())
+                .map(|crafter| crafter.level)
+                .max()
+                .is_none_or(|known| known >= sim::crafter_level(level))
+        });
+        if !learned {
+            let sources: Vec<String> = role_action
+                .jobs
+                .iter()
+                .map(|(source, level)| format!("{} at level {}", source, level))
+                .collect();
+            problems.push(format!(
+                "line {}: `{}` is learned by {}, but the gearsets set up for it are lower",
+                action.line,
+                role_action.name,
+                sources.join(" or ")
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(failure::format_err!("{}", problems.join("\n")))
+    }
+}
+
+// The lowest numbered gearset set up for a job, or 0 if there's none
+pub fn job_gearset(gearsets: &HashMap<u64, Gearset>, job: Option<Job>) -> u64 {
+    gearsets
+        .iter()
+        .filter(|(_, gearset)| job.is_some() && gearset.job == job)
+        .map(|(&number, _)| number)
+        .min()
+        .unwrap_or(0)
+}
+
+// The job, stats and specialist status of a task come from its gearset's
+// settings, though the job and specialist status can also be given on the
+// entry itself. Without a gearset, the one set up for the recipe's job is
+// used.
+pub fn resolve_entry(
+    entry: QueueEntry,
+    gearsets: &HashMap<u64, Gearset>,
+    items: &dyn ItemProvider,
+) -> Result<Task, Error> {
+    let gearset = gearsets.get(&entry.gearset).cloned().unwrap_or_default();
+    let job = match (entry.job, gearset.job) {
+        (Some(job), Some(gearset_job)) if job != gearset_job => {
+            return Err(failure::format_err!(
+                "`{}` is set to craft as {} but gearset {} is {}",
+                entry.item,
+                job,
+                entry.gearset,
+                gearset_job
+            ))
+        }
+        (job, gearset_job) => job.or(gearset_job),
+    };
+    let item = items.item(&entry.item, job)?;
+    log::info!("item information: {}", item);
+    entry.hq.check(&item)?;
+
+    let (number, gearset) = match entry.gearset {
+        0 => {
+            let number = job_gearset(gearsets, item.recipe.job);
+            if number > 0 {
+                log::info!("using gearset {} for `{}`", number, item.name);
+            }
+            (number, gearsets.get(&number).cloned().unwrap_or_default())
+        }
+        number => (number, gearset),
+    };
+    let specialist = entry.specialist.unwrap_or(gearset.specialist);
+    let steps = load_macro(&entry.macro_file, specialist, item.recipe.job, gearsets)?;
+
+    Ok(Task {
+        item,
+        count: entry.count,
+        index: entry.index,
+        steps,
+        gearset: number,
+        collectable: entry.collectable,
+        crafter: gearset.crafter,
+        hq: entry.hq,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::provider::LocalItems;
+
+    #[test]
+    fn queue_file() {
+        let entries = parse_queue(Path::new("src/test_queue.toml")).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            QueueEntry {
+                item: "Crimson Cider".to_string(),
+                count: 10,
+                macro_file: PathBuf::from("src/test_macro"),
+                gearset: 8,
+                index: 0,
+                collectable: false,
+                specialist: None,
+                job: Some(Job::CUL),
+                hq: HqStrategy::NqFirst,
+            }
+        );
+        assert_eq!(
+            entries[1],
+            QueueEntry {
+                item: "Sui-no-Sato Special".to_string(),
+                count: 1,
+                macro_file: PathBuf::from("src/test_macro"),
+                gearset: 0,
+                index: 1,
+                collectable: true,
+                specialist: Some(true),
+                job: None,
+                hq: "Sui-no-Sato Special Components=1".parse().unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn gearset_from_recipe_job() {
+        let items = LocalItems::load(Path::new("src/test_items.json")).unwrap();
+        let mut gearsets = HashMap::new();
+        gearsets.insert(
+            8,
+            Gearset {
+                job: Some(Job::CUL),
+                crafter: None,
+                specialist: false,
+            },
+        );
+        let entry = |gearset| QueueEntry {
+            item: "Crimson Cider".to_string(),
+            count: 1,
+            macro_file: PathBuf::from("src/test_macro"),
+            gearset,
+            index: 0,
+            collectable: false,
+            specialist: None,
+            job: None,
+            hq: HqStrategy::default(),
+        };
+
+        // No gearset given, so the CUL one is picked from the recipe
+        let task = resolve_entry(entry(0), &gearsets, &items).unwrap();
+        assert_eq!(task.gearset, 8);
+
+        let task = resolve_entry(entry(3), &gearsets, &items).unwrap();
+        assert_eq!(task.gearset, 3);
+
+        let task = resolve_entry(entry(0), &HashMap::new(), &items).unwrap();
+        assert_eq!(task.gearset, 0);
+    }
+
+    #[test]
+    fn specialist_from_gearset() {
+        let items = LocalItems::load(Path::new("src/test_items.json")).unwrap();
+        let mut gearsets = HashMap::new();
+        gearsets.insert(
+            8,
+            Gearset {
+                job: Some(Job::CUL),
+                crafter: None,
+                specialist: true,
+            },
+        );
+        let entry = |specialist| QueueEntry {
+            item: "Crimson Cider".to_string(),
+            count: 1,
+            macro_file: PathBuf::from("macros/specialist 4 star 70.txt"),
+            gearset: 0,
+            index: 0,
+            collectable: false,
+            specialist,
+            job: None,
+            hq: HqStrategy::default(),
+        };
+
+        assert!(resolve_entry(entry(None), &gearsets, &items).is_ok());
+        let err = resolve_entry(entry(Some(false)), &gearsets, &items).unwrap_err();
+        assert!(err.to_string().contains("can only be used by a specialist"));
+        assert!(resolve_entry(entry(None), &HashMap::new(), &items).is_err());
+        assert!(resolve_entry(entry(Some(true)), &HashMap::new(), &items).is_ok());
+    }
+
+    #[test]
+    fn role_action_levels() {
+        let path = Path::new("src/test_macro");
+        let mut gearsets = HashMap::new();
+        let alchemist = |level| Gearset {
+            job: Some(Job::ALC),
+            crafter: Some(Crafter {
+                level: sim::crafter_level(level),
+                craftsmanship: 1000,
+                control: 1000,
+                cp: 400,
+            }),
+            specialist: false,
+        };
+        gearsets.insert(5, alchemist(50));
+        assert!(load_macro(path, false, Some(Job::CUL), &gearsets).is_ok());
+
+        // Comfort Zone is an Alchemist action learned at 50
+        gearsets.insert(5, alchemist(40));
+        let err = load_macro(path, false, Some(Job::CUL), &gearsets).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid macro `src/test_macro`:\n\
+             line 1: `Comfort Zone` is learned by ALC at level 50, but the gearsets set up for it are lower"
+        );
+        // An Alchemist's own actions don't need slotting
+        assert!(load_macro(path, false, Some(Job::ALC), &gearsets).is_ok());
+    }
 }
@@ -0,0 +1,165 @@
+use failure::Error;
+use std::collections::HashMap;
+
+// Every wait Talan makes, in milliseconds. Most of these cover the time the
+// game takes to react to input, so they need to grow on slow connections.
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Timing {
+    pub key_press: u64,           // after every key press
+    pub char_press: u64,          // after every character typed into chat
+    pub chat_submit: u64,         // between typing a chat command and sending it
+    pub role_action: u64,         // after adding a role action
+    pub gearset_change: u64,      // after clearing role actions, before changing gearset
+    pub close_windows: u64,       // between cancelling menus and leaving the chat box
+    pub craft_log_open: u64,      // for the crafting log to appear
+    pub recipe_search: u64,       // between typing the item name and searching
+    pub search_results: u64,      // for recipe search results to populate
+    pub synthesis_start: u64,     // for the synthesis window to open
+    pub synthesis_end: u64,       // for a finished synthesis to return to the log
+    pub collectable_prompt: u64,  // for the collectable prompt to open
+    pub collectable_confirm: u64, // for the log to return after accepting a collectable
+    pub task_end: u64,            // after closing the crafting log at the end of a task
+    pub typed_action_short: u64,  // after typing an action with <wait.2>
+    pub typed_action_long: u64,   // after typing an action with <wait.3>
+    pub hotbar_action_short: u64, // after a hotbar action with <wait.2>
+    pub hotbar_action_long: u64,  // after a hotbar action with <wait.3>
+}
+
+impl Default for Timing {
+    fn default() -> Timing {
+        Timing::NORMAL
+    }
+}
+
+impl Timing {
+    pub const NORMAL: Timing = Timing {
+        key_press: 150,
+        char_press: 20,
+        chat_submit: 50,
+        role_action: 250,
+        gearset_change: 200,
+        close_windows: 1000,
+        craft_log_open: 1000,
+        recipe_search: 200,
+        search_results: 1000,
+        synthesis_start: 2000,
+        synthesis_end: 4000,
+        collectable_prompt: 1000,
+        collectable_confirm: 3000,
+        task_end: 2000,
+        // Typing an action takes at least 240ms plus the chat_submit wait,
+        // so these cover the rest of the 2.0 and 2.5 second GCD.
+        typed_action_short: 1700,
+        typed_action_long: 2200,
+        // A keybind only costs the key_press wait, so nearly all of the GCD
+        // is left. 100ms of slack is kept to absorb input lag.
+        hotbar_action_short: 1950,
+        hotbar_action_long: 2450,
+    };
+
+    // For a responsive client on a good connection. Action waits can't drop
+    // since they're bound by the GCD rather than the UI.
+    pub const FAST: Timing = Timing {
+        key_press: 100,
+        char_press: 10,
+        chat_submit: 30,
+        role_action: 150,
+        gearset_change: 150,
+        close_windows: 700,
+        craft_log_open: 700,
+        recipe_search: 150,
+        search_results: 700,
+        synthesis_start: 1500,
+        synthesis_end: 3000,
+        collectable_prompt: 800,
+        collectable_confirm: 2500,
+        task_end: 1500,
+        typed_action_short: 1700,
+        typed_action_long: 2200,
+        hotbar_action_short: 1950,
+        hotbar_action_long: 2450,
+    };
+
+    pub const HIGH_LATENCY: Timing = Timing {
+        key_press: 250,
+        char_press: 30,
+        chat_submit: 100,
+        role_action: 500,
+        gearset_change: 500,
+        close_windows: 2000,
+        craft_log_open: 2000,
+        recipe_search: 500,
+        search_results: 2000,
+        synthesis_start: 3500,
+        synthesis_end: 6000,
+        collectable_prompt: 2000,
+        collectable_confirm: 4500,
+        task_end: 3000,
+        typed_action_short: 1900,
+        typed_action_long: 2400,
+        hotbar_action_short: 2200,
+        hotbar_action_long: 2700,
+    };
+
+    pub fn profile(name: &str) -> Result<Timing, Error> {
+        match &*name.to_lowercase() {
+            "fast" => Ok(Timing::FAST),
+            "normal" => Ok(Timing::NORMAL),
+            "high-latency" => Ok(Timing::HIGH_LATENCY),
+            _ => Err(failure::format_err!(
+                "unknown timing profile `{}`, expected fast, normal or high-latency",
+                name
+            )),
+        }
+    }
+
+    // Replace individual fields by name, as found in the `[timing]` table
+    pub fn with_overrides(self, overrides: &HashMap<String, String>) -> Result<Timing, Error> {
+        let mut fields = match serde_json::to_value(self)? {
+            serde_json::Value::Object(fields) => fields,
+            _ => unreachable!(),
+        };
+        for (name, value) in overrides {
+            if !fields.contains_key(name) {
+                return Err(failure::format_err!("unknown timing field `{}`", name));
+            }
+            let ms = value.parse::<u64>().map_err(|_| {
+                failure::format_err!("timing field `{}` must be a number of ms", name)
+            })?;
+            fields.insert(name.clone(), ms.into());
+        }
+        Ok(serde_json::from_value(serde_json::Value::Object(fields))?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timing_profiles() {
+        assert_eq!(Timing::profile("normal").unwrap(), Timing::default());
+        assert_eq!(
+            Timing::profile("High-Latency").unwrap(),
+            Timing::HIGH_LATENCY
+        );
+        assert!(Timing::profile("ludicrous").is_err());
+    }
+
+    #[test]
+    fn timing_overrides() {
+        let mut overrides = HashMap::new();
+        overrides.insert("synthesis_start".to_string(), "3000".to_string());
+        let timing = Timing::FAST.with_overrides(&overrides).unwrap();
+        assert_eq!(timing.synthesis_start, 3000);
+        assert_eq!(timing.key_press, Timing::FAST.key_press);
+
+        overrides.insert("synthesis_start".to_string(), "soon".to_string());
+        assert!(Timing::FAST.with_overrides(&overrides).is_err());
+
+        overrides.clear();
+        overrides.insert("coffee_break".to_string(), "100".to_string());
+        assert!(Timing::FAST.with_overrides(&overrides).is_err());
+    }
+}
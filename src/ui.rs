@@ -1,164 +1,325 @@
-pub use self::ui_impl::WinHandle;
-
-use std::thread::sleep;
+use crate::keybinds::{Keybind, Keybinds};
+use crate::timing::Timing;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::{RwLock, RwLockReadGuard};
 use std::time::Duration;
 
-// Creating these simplifies the wait code for the craft module
-pub fn wait_ms(ms: u64) {
-    sleep(Duration::from_millis(ms));
+lazy_static::lazy_static! {
+    // The keybinds are read once from config at startup and shared by every
+    // navigation call. Until then the game's default numpad binds are used.
+    static ref KEYBINDS: RwLock<Keybinds> = RwLock::new(Keybinds::default());
+    // Likewise every wait comes from the active timing profile
+    static ref TIMING: RwLock<Timing> = RwLock::new(Timing::default());
+}
+
+pub fn set_keybinds(keybinds: Keybinds) {
+    *KEYBINDS.write().unwrap() = keybinds;
+}
+
+pub fn keybinds() -> RwLockReadGuard<'static, Keybinds> {
+    KEYBINDS.read().unwrap()
+}
+
+pub fn set_timing(timing: Timing) {
+    *TIMING.write().unwrap() = timing;
+}
+
+pub fn timing() -> Timing {
+    *TIMING.read().unwrap()
+}
+
+// The lowest level operations needed to drive the game. Everything in this
+// module is built on top of these, so swapping the backend changes where
+// input goes without touching any of the crafting logic.
+pub trait InputBackend {
+    fn send_key(&self, key: Keybind);
+    fn send_char(&self, c: char);
+    fn wait(&self, duration: Duration);
+}
+
+// A cheaply cloneable handle to the backend receiving input.
+#[derive(Clone)]
+pub struct WinHandle(Rc<dyn InputBackend>);
+
+impl WinHandle {
+    pub fn new<B: InputBackend + 'static>(backend: B) -> WinHandle {
+        WinHandle(Rc::new(backend))
+    }
 }
 
-pub fn wait_secs(s: u64) {
-    sleep(Duration::from_secs(s));
+impl fmt::Debug for WinHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WinHandle")
+    }
 }
 
-pub fn cursor_down(window: WinHandle) {
+// Creating these simplifies the wait code for the craft module
+pub fn wait_ms(window: &WinHandle, ms: u64) {
+    window.0.wait(Duration::from_millis(ms));
+}
+
+pub fn cursor_down(window: &WinHandle) {
     log::trace!("Down");
-    ui_impl::cursor_down(window);
+    send_key(window, keybinds().cursor_down);
 }
 
-pub fn cursor_up(window: WinHandle) {
+pub fn cursor_up(window: &WinHandle) {
     log::trace!("Up");
-    ui_impl::cursor_up(window);
+    send_key(window, keybinds().cursor_up);
 }
 
-pub fn cursor_left(window: WinHandle) {
+pub fn cursor_left(window: &WinHandle) {
     log::trace!("Left");
-    ui_impl::cursor_left(window);
+    send_key(window, keybinds().cursor_left);
 }
 
-pub fn cursor_right(window: WinHandle) {
+pub fn cursor_right(window: &WinHandle) {
     log::trace!("Right");
-    ui_impl::cursor_right(window);
+    send_key(window, keybinds().cursor_right);
 }
 
-pub fn move_backward(window: WinHandle) {
+pub fn move_backward(window: &WinHandle) {
     log::trace!("<-");
-    ui_impl::move_backward(window);
+    send_key(window, keybinds().cycle_backward);
 }
 
-pub fn _move_forward(window: WinHandle) {
+pub fn _move_forward(window: &WinHandle) {
     log::trace!("->");
-    ui_impl::move_forward(window);
+    send_key(window, keybinds().cycle_forward);
 }
 
-pub fn confirm(window: WinHandle) {
+pub fn confirm(window: &WinHandle) {
     log::trace!("Confirm");
-    ui_impl::confirm(window);
+    send_key(window, keybinds().confirm);
 }
 
-pub fn cancel(window: WinHandle) {
+pub fn cancel(window: &WinHandle) {
     log::trace!("Cancel");
-    ui_impl::cancel(window);
+    send_key(window, keybinds().cancel);
 }
 
-pub fn enter(window: WinHandle) {
+pub fn enter(window: &WinHandle) {
     log::trace!("Enter");
-    ui_impl::enter(window);
+    send_key(window, keybinds().chat);
 }
 
-pub fn escape(window: WinHandle) {
+pub fn escape(window: &WinHandle) {
     log::trace!("Escape");
-    ui_impl::escape(window);
+    send_key(window, keybinds().close_window);
 }
 
-pub fn open_craft_window(window: WinHandle) {
+pub fn open_craft_window(window: &WinHandle) {
     log::trace!("CraftWindow");
-    ui_impl::open_craft_window(window);
+    send_key(window, keybinds().crafting_log);
+}
+
+// Every key press is followed by a short pause so the game registers it
+// before the next one arrives.
+pub fn send_key(window: &WinHandle, key: Keybind) {
+    window.0.send_key(key);
+    wait_ms(window, timing().key_press);
+}
+
+pub fn send_char(window: &WinHandle, c: char) {
+    window.0.send_char(c);
+    wait_ms(window, timing().char_press);
+}
+
+// Find the running game and return a handle sending input to it
+pub fn find_window() -> Option<WinHandle> {
+    ui_impl::find_window().map(WinHandle::new)
+}
+
+// A single event captured by the RecordingBackend
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Key(Keybind),
+    Char(char),
+    Wait(Duration),
+}
+
+// Captures input instead of sending it anywhere, and returns from waits
+// immediately. The recorded stream is shared between clones so it can be
+// read back after the backend is handed to a WinHandle.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingBackend {
+    inputs: Rc<RefCell<Vec<Input>>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> RecordingBackend {
+        RecordingBackend::default()
+    }
+
+    pub fn inputs(&self) -> Vec<Input> {
+        self.inputs.borrow().clone()
+    }
+
+    #[cfg(test)]
+    pub fn render(&self) -> String {
+        render_inputs(&self.inputs.borrow())
+    }
+}
+
+// Render recorded input one event per line. Characters are typed with a
+// wait after each one, so runs of them are folded into a single line to
+// keep the output readable.
+pub fn render_inputs(inputs: &[Input]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < inputs.len() {
+        match inputs[i] {
+            Input::Key(key) => out.push_str(&format!("key {}\n", key)),
+            Input::Wait(d) => out.push_str(&format!("wait {}ms\n", d.as_millis())),
+            Input::Char(_) => {
+                let mut text = String::new();
+                let mut per_char = None;
+                while let Some(Input::Char(c)) = inputs.get(i) {
+                    text.push(*c);
+                    i += 1;
+                    match inputs.get(i) {
+                        Some(Input::Wait(d)) if per_char.is_none_or(|p| p == *d) => {
+                            per_char = Some(*d);
+                            i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                match per_char {
+                    Some(d) => {
+                        out.push_str(&format!("type {:?} ({}ms/char)\n", text, d.as_millis()))
+                    }
+                    None => out.push_str(&format!("type {:?}\n", text)),
+                }
+                continue;
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+// Sum of every wait in the recording, which is close to how long the
+// sequence takes to run since sending input itself is nearly instant.
+pub fn total_wait(inputs: &[Input]) -> Duration {
+    inputs
+        .iter()
+        .map(|input| match input {
+            Input::Wait(d) => *d,
+            _ => Duration::default(),
+        })
+        .sum()
 }
 
-pub fn send_char(window: WinHandle, c: char) {
-    ui_impl::send_char(window, c);
+impl InputBackend for RecordingBackend {
+    fn send_key(&self, key: Keybind) {
+        self.inputs.borrow_mut().push(Input::Key(key));
+    }
+
+    fn send_char(&self, c: char) {
+        self.inputs.borrow_mut().push(Input::Char(c));
+    }
+
+    fn wait(&self, duration: Duration) {
+        self.inputs.borrow_mut().push(Input::Wait(duration));
+    }
 }
 
-pub fn get_window(hwnd: &mut WinHandle) -> bool {
-    ui_impl::get_window(hwnd)
+// Compare a rendered recording against a golden file in src/golden. Setting
+// UPDATE_GOLDEN=1 rewrites the file instead, for when a change to the
+// input sequence is intended.
+#[cfg(test)]
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = std::path::Path::new("src/golden").join(format!("{}.txt", name));
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("reading golden file `{}`: {}", path.display(), e));
+    assert!(
+        expected == actual,
+        "input sequence does not match `{}`\n--- expected\n{}\n--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    );
 }
 
 #[cfg(windows)]
 mod ui_impl {
-    use failure::Error;
+    use super::InputBackend;
+    use crate::keybinds::{Key, Keybind};
     use std::ffi::CStr;
     use std::ptr::null_mut;
-    use std::sync::Once;
     use std::thread::sleep;
     use std::time::Duration;
     use winapi::shared::basetsd::LONG_PTR;
     use winapi::shared::minwindef::{BOOL, UINT};
-    pub use winapi::shared::windef::{HWND, HWND__};
+    pub use winapi::shared::windef::HWND;
     pub use winapi::um::winuser::*;
     pub use winapi::um::winuser::{EnumWindows, GetWindowTextA, PostMessageA};
 
-    pub type WinHandle = HWND;
+    // Sends input to the game window by posting messages to its queue
+    pub struct Window {
+        hwnd: HWND,
+    }
 
-    // TODO: Configurable keybinds
-    const KEY_UP: i32 = VK_NUMPAD8;
-    const KEY_DOWN: i32 = VK_NUMPAD2;
-    const KEY_LEFT: i32 = VK_NUMPAD4;
-    const KEY_RIGHT: i32 = VK_NUMPAD6;
-    const KEY_CONFIRM: i32 = VK_NUMPAD0;
-    const KEY_FORWARD: i32 = VK_NUMPAD9;
-    const KEY_BACKWARD: i32 = VK_NUMPAD7;
-    const KEY_CANCEL: i32 = VK_DECIMAL;
-    const KEY_ENTER: i32 = VK_RETURN;
+    impl InputBackend for Window {
+        // Modifiers are held down around the key press itself
+        fn send_key(&self, key: Keybind) {
+            if key.ctrl {
+                send_msg(self.hwnd, WM_KEYDOWN, VK_CONTROL);
+            }
+            if key.shift {
+                send_msg(self.hwnd, WM_KEYDOWN, VK_SHIFT);
+            }
+            let vk = virtual_key(key.key);
+            send_msg(self.hwnd, WM_KEYDOWN, vk);
+            send_msg(self.hwnd, WM_KEYUP, vk);
+            if key.shift {
+                send_msg(self.hwnd, WM_KEYUP, VK_SHIFT);
+            }
+            if key.ctrl {
+                send_msg(self.hwnd, WM_KEYUP, VK_CONTROL);
+            }
+        }
 
-    // Common public methods the ui_impl modules export
-    #[inline]
-    pub fn cursor_down(window: HWND) {
-        send_key(window, KEY_DOWN);
-    }
-    #[inline]
-    pub fn cursor_up(window: HWND) {
-        send_key(window, KEY_UP);
-    }
-    #[inline]
-    pub fn cursor_left(window: HWND) {
-        send_key(window, KEY_LEFT);
-    }
-    #[inline]
-    pub fn cursor_right(window: HWND) {
-        send_key(window, KEY_RIGHT);
-    }
-    #[inline]
-    pub fn move_backward(window: HWND) {
-        send_key(window, KEY_BACKWARD)
-    }
-    #[inline]
-    pub fn move_forward(window: HWND) {
-        send_key(window, KEY_FORWARD);
-    }
-    #[inline]
-    pub fn confirm(window: HWND) {
-        send_key(window, KEY_CONFIRM);
-    }
-    #[inline]
-    pub fn cancel(window: HWND) {
-        send_key(window, KEY_CANCEL);
-    }
-    #[inline]
-    pub fn enter(window: HWND) {
-        send_key(window, KEY_ENTER);
-    }
-    #[inline]
-    pub fn escape(window: HWND) {
-        send_key(window, VK_ESCAPE);
-    }
-    #[inline]
-    pub fn open_craft_window(window: HWND) {
-        send_key(window, 'N' as i32);
-    }
+        fn send_char(&self, c: char) {
+            send_msg(self.hwnd, WM_CHAR, c as i32);
+        }
 
-    #[inline]
-    pub fn send_key(window: HWND, c: i32) {
-        send_msg(window, WM_KEYDOWN, c);
-        send_msg(window, WM_KEYUP, c);
-        sleep(Duration::from_millis(150));
+        fn wait(&self, duration: Duration) {
+            sleep(duration);
+        }
     }
 
-    #[inline]
-    pub fn send_char(window: HWND, c: char) {
-        send_msg(window, WM_CHAR, c as i32);
-        sleep(Duration::from_millis(20));
+    // Translate a configured key into its Windows virtual-key code
+    fn virtual_key(key: Key) -> i32 {
+        match key {
+            Key::Char(c) if c.is_ascii_alphanumeric() => c.to_ascii_uppercase() as i32,
+            Key::Char('[') => VK_OEM_4,
+            Key::Char(']') => VK_OEM_6,
+            Key::Char('=') => VK_OEM_PLUS,
+            Key::Char('-') => VK_OEM_MINUS,
+            Key::Char(',') => VK_OEM_COMMA,
+            Key::Char('.') => VK_OEM_PERIOD,
+            Key::Char('/') => VK_OEM_2,
+            Key::Char(';') => VK_OEM_1,
+            Key::Char('\'') => VK_OEM_7,
+            Key::Char('\\') => VK_OEM_5,
+            Key::Char('`') => VK_OEM_3,
+            Key::Char(c) => panic!("no virtual key for `{}`", c),
+            Key::Numpad(n) => VK_NUMPAD0 + i32::from(n),
+            Key::Function(n) => VK_F1 + i32::from(n) - 1,
+            Key::Decimal => VK_DECIMAL,
+            Key::Enter => VK_RETURN,
+            Key::Escape => VK_ESCAPE,
+            Key::Space => VK_SPACE,
+            Key::Tab => VK_TAB,
+        }
     }
 
     // This callback is called for every window the user32 EnumWindows call finds
@@ -183,12 +344,14 @@ mod ui_impl {
     // Return the handle of the FFXIV window. The EnumWindow return is inverted because
     // we can live in a better world than one where 0 is success.
     // TODO: Figure out how to return good errors here.
-    pub fn get_window(hwnd: &mut HWND) -> bool {
-        unsafe {
-            match EnumWindows(Some(enum_callback), hwnd as *mut HWND as LONG_PTR) {
-                0 => true,
-                _ => false,
-            }
+    pub fn find_window() -> Option<Window> {
+        let mut hwnd: HWND = null_mut();
+        let found =
+            unsafe { EnumWindows(Some(enum_callback), &mut hwnd as *mut HWND as LONG_PTR) == 0 };
+        if found {
+            Some(Window { hwnd })
+        } else {
+            None
         }
     }
 
@@ -200,38 +363,464 @@ mod ui_impl {
     }
 }
 
-#[allow(dead_code)]
-#[cfg(not(windows))]
+#[cfg(target_os = "linux")]
+mod ui_impl {
+    use super::InputBackend;
+    use crate::keybinds::{Key, Keybind};
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_uint, c_ulong};
+    use std::ptr::null;
+    use std::ptr::null_mut;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use x11_dl::keysym::*;
+    use x11_dl::xlib;
+    // x11-dl names the libXtst bindings after the wrong extension
+    use x11_dl::xtest::Xf86vmode as XTest;
+
+    // How key events reach the game. SendEvent posts them straight to the
+    // game's window, much like PostMessage on Windows, so the game doesn't
+    // need focus. XTest fakes input at the server level, which Wine always
+    // accepts, but the game window is focused first so the keys land there.
+    enum Delivery {
+        SendEvent,
+        XTest(XTest),
+    }
+
+    // Sends input to the game running under Wine/Proton through X11. The
+    // libraries are loaded at runtime so Talan still starts on systems
+    // without them.
+    pub struct Window {
+        xlib: xlib::Xlib,
+        display: *mut xlib::Display,
+        window: xlib::Window,
+        delivery: Delivery,
+    }
+
+    impl Window {
+        // Open a connection to the display and wrap the given window id.
+        // Setting TALAN_XTEST=1 in the environment switches delivery to XTest.
+        pub fn open(display_name: Option<&CStr>, window: xlib::Window) -> Option<Window> {
+            let xlib = xlib::Xlib::open().ok()?;
+            let display =
+                unsafe { (xlib.XOpenDisplay)(display_name.map_or(null(), |n| n.as_ptr())) };
+            if display.is_null() {
+                return None;
+            }
+
+            let window = Window {
+                xlib,
+                display,
+                window,
+                delivery: Delivery::SendEvent,
+            };
+            match std::env::var("TALAN_XTEST") {
+                Ok(ref v) if v == "1" => window.with_xtest(),
+                _ => Some(window),
+            }
+        }
+
+        // Switch delivery to XTest, if the library is there
+        pub fn with_xtest(mut self) -> Option<Window> {
+            self.delivery = Delivery::XTest(XTest::open().ok()?);
+            Some(self)
+        }
+
+        fn keycode(&self, keysym: c_uint) -> Option<c_uint> {
+            match unsafe { (self.xlib.XKeysymToKeycode)(self.display, c_ulong::from(keysym)) } {
+                0 => None,
+                keycode => Some(c_uint::from(keycode)),
+            }
+        }
+
+        // Whether the keysym needs shift on the key it lives on, as with
+        // upper case letters. Keysyms on any other level, such as those
+        // behind AltGr, can't be typed and give None.
+        fn needs_shift(&self, keycode: c_uint, keysym: c_uint) -> Option<bool> {
+            let level = |level| unsafe {
+                (self.xlib.XkbKeycodeToKeysym)(self.display, keycode as u8, 0, level)
+            };
+            if level(0) == c_ulong::from(keysym) {
+                Some(false)
+            } else if level(1) == c_ulong::from(keysym) {
+                Some(true)
+            } else {
+                None
+            }
+        }
+
+        fn press(&self, keycode: c_uint, state: c_uint, down: bool) {
+            match self.delivery {
+                Delivery::SendEvent => {
+                    let event = xlib::XKeyEvent {
+                        type_: if down {
+                            xlib::KeyPress
+                        } else {
+                            xlib::KeyRelease
+                        },
+                        serial: 0,
+                        send_event: xlib::True,
+                        display: self.display,
+                        window: self.window,
+                        root: unsafe { (self.xlib.XDefaultRootWindow)(self.display) },
+                        subwindow: 0,
+                        time: xlib::CurrentTime,
+                        x: 1,
+                        y: 1,
+                        x_root: 1,
+                        y_root: 1,
+                        state,
+                        keycode,
+                        same_screen: xlib::True,
+                    };
+                    let mask = if down {
+                        xlib::KeyPressMask
+                    } else {
+                        xlib::KeyReleaseMask
+                    };
+                    let mut event = xlib::XEvent::from(event);
+                    unsafe {
+                        (self.xlib.XSendEvent)(
+                            self.display,
+                            self.window,
+                            xlib::True,
+                            mask,
+                            &mut event,
+                        );
+                    }
+                }
+                Delivery::XTest(ref xtest) => unsafe {
+                    (xtest.XTestFakeKeyEvent)(
+                        self.display,
+                        keycode,
+                        down as i32,
+                        xlib::CurrentTime,
+                    );
+                },
+            }
+        }
+
+        // Press and release a keycode with the given modifiers held
+        fn tap(&self, keycode: c_uint, shift: bool, ctrl: bool) {
+            if let Delivery::XTest(_) = self.delivery {
+                unsafe {
+                    (self.xlib.XSetInputFocus)(
+                        self.display,
+                        self.window,
+                        xlib::RevertToParent,
+                        xlib::CurrentTime,
+                    );
+                }
+            }
+
+            let mut state = 0;
+            let mut modifiers = vec![];
+            if ctrl {
+                modifiers.extend(self.keycode(XK_Control_L));
+            }
+            if shift {
+                modifiers.extend(self.keycode(XK_Shift_L));
+            }
+            for modifier in &modifiers {
+                self.press(*modifier, state, true);
+            }
+            if ctrl {
+                state |= xlib::ControlMask;
+            }
+            if shift {
+                state |= xlib::ShiftMask;
+            }
+            self.press(keycode, state, true);
+            self.press(keycode, state, false);
+            for modifier in modifiers.iter().rev() {
+                self.press(*modifier, state, false);
+            }
+            unsafe {
+                (self.xlib.XFlush)(self.display);
+            }
+        }
+    }
+
+    impl Drop for Window {
+        fn drop(&mut self) {
+            unsafe {
+                (self.xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+
+    // Translate a configured key into its X11 keysym
+    pub fn keysym(key: Key) -> c_uint {
+        match key {
+            // Latin-1 keysyms share their values with ASCII
+            Key::Char(c) => c as c_uint,
+            Key::Numpad(n) => XK_KP_0 + c_uint::from(n),
+            Key::Function(n) => XK_F1 + c_uint::from(n) - 1,
+            Key::Decimal => XK_KP_Decimal,
+            Key::Enter => XK_Return,
+            Key::Escape => XK_Escape,
+            Key::Space => XK_space,
+            Key::Tab => XK_Tab,
+        }
+    }
+
+    // Keysyms for characters outside Latin-1 are offset into the Unicode range
+    pub fn char_keysym(c: char) -> c_uint {
+        match c as c_uint {
+            0x0a => XK_Return,
+            code @ 0x20..=0xff => code,
+            code => 0x0100_0000 + code,
+        }
+    }
+
+    impl InputBackend for Window {
+        fn send_key(&self, key: Keybind) {
+            match self.keycode(keysym(key.key)) {
+                Some(keycode) => self.tap(keycode, key.shift, key.ctrl),
+                None => log::warn!("no keycode for `{}`, key not sent", key),
+            }
+        }
+
+        fn send_char(&self, c: char) {
+            let keysym = char_keysym(c);
+            let shift = self
+                .keycode(keysym)
+                .and_then(|keycode| Some((keycode, self.needs_shift(keycode, keysym)?)));
+            match shift {
+                Some((keycode, shift)) => self.tap(keycode, shift, false),
+                None => log::warn!(
+                    "`{}` can't be typed with the current keyboard layout, character not sent",
+                    c
+                ),
+            }
+        }
+
+        fn wait(&self, duration: Duration) {
+            sleep(duration);
+        }
+    }
+
+    // Walk the window tree looking for a window whose title contains
+    // `title`. Wine creates the top level window as a child of the window
+    // manager's frame, so every level has to be searched.
+    pub fn search(
+        xlib: &xlib::Xlib,
+        display: *mut xlib::Display,
+        window: xlib::Window,
+        title: &str,
+    ) -> Option<xlib::Window> {
+        unsafe {
+            let mut name: *mut c_char = null_mut();
+            if (xlib.XFetchName)(display, window, &mut name) != 0 && !name.is_null() {
+                let found = CStr::from_ptr(name).to_string_lossy().contains(title);
+                (xlib.XFree)(name as *mut _);
+                if found {
+                    return Some(window);
+                }
+            }
+
+            let mut root = 0;
+            let mut parent = 0;
+            let mut children: *mut xlib::Window = null_mut();
+            let mut count: c_uint = 0;
+            if (xlib.XQueryTree)(
+                display,
+                window,
+                &mut root,
+                &mut parent,
+                &mut children,
+                &mut count,
+            ) == 0
+            {
+                return None;
+            }
+            let mut result = None;
+            if !children.is_null() {
+                for i in 0..count as usize {
+                    result = search(xlib, display, *children.add(i), title);
+                    if result.is_some() {
+                        break;
+                    }
+                }
+                (xlib.XFree)(children as *mut _);
+            }
+            result
+        }
+    }
+
+    pub fn find_titled(display_name: Option<&CStr>, title: &str) -> Option<Window> {
+        let mut window = Window::open(display_name, 0)?;
+        let root = unsafe { (window.xlib.XDefaultRootWindow)(window.display) };
+        window.window = search(&window.xlib, window.display, root, title)?;
+        println!("Found XIV window: {:#x}", window.window);
+        Some(window)
+    }
+
+    pub fn find_window() -> Option<Window> {
+        find_titled(None, "FINAL FANTASY XIV")
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
 mod ui_impl {
-    pub type WinHandle = *mut u64;
-
-    // Common public methods the ui_impl modules export
-    #[inline]
-    pub fn cursor_down(_: WinHandle) {}
-    #[inline]
-    pub fn cursor_up(_: WinHandle) {}
-    #[inline]
-    pub fn cursor_left(_: WinHandle) {}
-    #[inline]
-    pub fn cursor_right(_: WinHandle) {}
-    #[inline]
-    pub fn move_backward(_: WinHandle) {}
-    #[inline]
-    pub fn move_forward(_: WinHandle) {}
-    #[inline]
-    pub fn enter(_: WinHandle) {}
-    #[inline]
-    pub fn confirm(_: WinHandle) {}
-    #[inline]
-    pub fn cancel(_: WinHandle) {}
-    #[inline]
-    pub fn escape(_: WinHandle) {}
-    #[inline]
-    pub fn send_char(_: WinHandle, _: char) {}
-    #[inline]
-    pub fn open_craft_window(_: WinHandle) {}
-    #[inline]
-    pub fn get_window(_: &mut WinHandle) -> bool {
-        true
+    use super::InputBackend;
+    use crate::keybinds::Keybind;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    // There is no game window to talk to on other platforms, so input is
+    // dropped. Waits are still honored so runs take as long as they would
+    // on Windows.
+    pub struct Window;
+
+    impl InputBackend for Window {
+        fn send_key(&self, _: Keybind) {}
+        fn send_char(&self, _: char) {}
+        fn wait(&self, duration: Duration) {
+            sleep(duration);
+        }
+    }
+
+    pub fn find_window() -> Option<Window> {
+        Some(Window)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keybinds::Key;
+
+    #[test]
+    fn recording_backend() {
+        let backend = RecordingBackend::new();
+        let window = WinHandle::new(backend.clone());
+        confirm(&window);
+        send_char(&window, '/');
+        send_char(&window, 'a');
+        wait_ms(&window, 1000);
+
+        assert_eq!(
+            backend.inputs(),
+            vec![
+                Input::Key(Keybind::new(Key::Numpad(0))),
+                Input::Wait(Duration::from_millis(150)),
+                Input::Char('/'),
+                Input::Wait(Duration::from_millis(20)),
+                Input::Char('a'),
+                Input::Wait(Duration::from_millis(20)),
+                Input::Wait(Duration::from_secs(1)),
+            ]
+        );
+        assert_eq!(
+            backend.render(),
+            "key num0\nwait 150ms\ntype \"/a\" (20ms/char)\nwait 1000ms\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn x11_keysyms() {
+        use x11_dl::keysym::*;
+        assert_eq!(ui_impl::keysym(Key::Char('a')), XK_a);
+        assert_eq!(ui_impl::keysym(Key::Char('[')), XK_bracketleft);
+        assert_eq!(ui_impl::keysym(Key::Numpad(8)), XK_KP_8);
+        assert_eq!(ui_impl::keysym(Key::Function(12)), XK_F12);
+        assert_eq!(ui_impl::keysym(Key::Decimal), XK_KP_Decimal);
+        assert_eq!(ui_impl::char_keysym('"'), XK_quotedbl);
+        assert_eq!(ui_impl::char_keysym('A'), XK_A);
+        assert_eq!(ui_impl::char_keysym('\u{e9}'), XK_eacute);
+    }
+
+    // Send keys to a window of our own through the X11 backend and check
+    // they arrive. This needs an X server, such as the one `xvfb-run cargo
+    // test` starts, and is skipped without one.
+    #[cfg(target_os = "linux")]
+    fn x11_delivery(title: &str, xtest: bool) {
+        use std::ptr::null;
+        use x11_dl::keysym::*;
+        use x11_dl::xlib;
+
+        let x = match xlib::Xlib::open() {
+            Ok(x) if std::env::var_os("DISPLAY").is_some() => x,
+            _ => {
+                eprintln!("no X display, skipping the X11 backend test");
+                return;
+            }
+        };
+        let (display, window) = unsafe {
+            let display = (x.XOpenDisplay)(null());
+            assert!(!display.is_null(), "can't open the X display");
+            let root = (x.XDefaultRootWindow)(display);
+            let window = (x.XCreateSimpleWindow)(display, root, 0, 0, 100, 100, 0, 0, 0);
+            let name = std::ffi::CString::new(title).unwrap();
+            (x.XStoreName)(display, window, name.as_ptr());
+            (x.XSelectInput)(display, window, xlib::KeyPressMask);
+            (x.XMapWindow)(display, window);
+            (x.XSync)(display, xlib::False);
+            (display, window)
+        };
+
+        let game = ui_impl::find_titled(None, title).expect("test window not found");
+        let game = if xtest {
+            game.with_xtest().expect("XTest is not available")
+        } else {
+            game
+        };
+        game.send_key(Keybind::new(Key::Numpad(8)));
+        game.send_key("^r".parse().unwrap());
+        game.send_char('A');
+
+        // The keys come in over the backend's own connection, so give them
+        // a moment to arrive
+        let mut presses = vec![];
+        for _ in 0..50 {
+            unsafe {
+                (x.XSync)(display, xlib::False);
+                while (x.XPending)(display) > 0 {
+                    let mut event: xlib::XEvent = std::mem::zeroed();
+                    (x.XNextEvent)(display, &mut event);
+                    if event.get_type() == xlib::KeyPress {
+                        let key = xlib::XKeyEvent::from(event);
+                        assert_eq!(key.window, window);
+                        presses.push((key.keycode, key.state));
+                    }
+                }
+            }
+            if presses.len() >= 5 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        let keycode =
+            |keysym| unsafe { u32::from((x.XKeysymToKeycode)(display, u64::from(keysym))) };
+        let control = (keycode(XK_Control_L), 0);
+        let shift = (keycode(XK_Shift_L), 0);
+        assert_eq!(
+            presses,
+            vec![
+                (keycode(XK_KP_8), 0),
+                control,
+                (keycode(XK_r), xlib::ControlMask),
+                shift,
+                (keycode(XK_a), xlib::ShiftMask),
+            ]
+        );
+        unsafe {
+            (x.XCloseDisplay)(display);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn x11_send_event() {
+        x11_delivery("FINAL FANTASY XIV (talan send event test)", false);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn x11_xtest() {
+        x11_delivery("FINAL FANTASY XIV (talan xtest test)", true);
     }
 }